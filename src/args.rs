@@ -1,44 +1,600 @@
-use clap::{Parser, Subcommand};
+use crate::chunk::Chunk;
+use crate::encoding::{self, Interpretation};
+use crate::png::Png;
+use crate::timestamp::{Timestamp, TimestampError};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// don't print error messages. The exit code still reports failure
+    #[arg(long, global = true)]
+    pub quiet_errors: bool,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     /// Hide a message in a PNG file
     Encode {
-        /// path to the PNG file
+        /// path to the PNG file, or `-` to read it from stdin and write the result to stdout
         file: String,
         /// type of chunk to hide the message in
         chunk_type: String,
-        /// message to hide in the PNG file
-        message: String,
-        /// save a modified a copy
-        output_file: Option<String>,
+        #[command(flatten)]
+        source: MessageSource,
+        #[command(flatten)]
+        destination: EncodeDestination,
+        #[command(flatten)]
+        placement: ChunkPlacement,
+        #[command(flatten)]
+        framing: EncodeFraming,
+        #[command(flatten)]
+        time_filter: TimeFilter,
+        #[command(flatten)]
+        batch: Batch,
     },
 
     /// Read a message from a PNG file
     Decode {
-        /// path to the PNG file
+        /// path to the PNG file, or `-` to read it from stdin
         file: String,
         /// type of chunk to look for a message in. Must be 4 alphabetic characters
+        #[arg(required_unless_present = "type_bytes")]
+        chunk_type: Option<String>,
+        /// look for a chunk whose type is exactly these 4 bytes, given in hex, instead of a
+        /// chunk type. Finds chunks written by tools that don't use letters, e.g. DEADBEEF
+        #[arg(long, value_name = "HEX", value_parser = parse_type_bytes, conflicts_with = "chunk_type")]
+        type_bytes: Option<[u8; 4]>,
+        #[command(flatten)]
+        output: DecodeOutput,
+        /// accept chunks with an incorrect CRC or a non-standard signature, warning about each one.
+        /// The file is not modified
+        #[arg(long)]
+        fix_crc: bool,
+        #[command(flatten)]
+        framing: DecodeFraming,
+        /// more PNG files to decode the same way, each after a line with its path. Failures don't
+        /// stop the others and are listed together at the end
+        #[arg(long = "also", value_name = "PATH", num_args = 1.., conflicts_with = "output")]
+        more_files: Vec<String>,
+    },
+
+    /// Hide a message in the least significant bit of each pixel sample rather than in a chunk,
+    /// so it doesn't show up in the chunk list. The image data is stored uncompressed, so the file
+    /// grows to about the size of the raw image. Needs a non-interlaced, non-palette image with
+    /// 8-bit samples
+    Embed {
+        /// path to the PNG file, or `-` to read it from stdin and write the result to stdout
+        file: String,
+        /// message to hide in the pixels
+        message: String,
+        /// save a modified copy
+        output_file: Option<String>,
+    },
+
+    /// Print a message hidden in the pixels by `embed`
+    Reveal {
+        /// path to the PNG file, or `-` to read it from stdin
+        file: String,
+    },
+
+    /// Add a chunk holding the bytes of a file at an exact position, even before IHDR or after
+    /// IEND, e.g. to craft test files
+    Insert {
+        /// path to the PNG file
+        file: String,
+        /// type of chunk to add. Must be 4 alphabetic characters
         chunk_type: String,
+        /// file whose bytes become the chunk data
+        #[arg(long, value_name = "PATH")]
+        data_file: String,
+        /// position the chunk ends up at, counting from 0. Can be at most the number of chunks
+        #[arg(long, value_name = "N")]
+        index: usize,
+        /// save a modified copy
+        output_file: Option<String>,
     },
 
     /// Remove the first occurrence of a given chunk type from a PNG file
     Remove {
-        /// path to the PNG file
+        /// path to the PNG file, or `-` to read it from stdin and write the result to stdout
         file: String,
         /// type of chunk to remove. Must be 4 alphabetic characters
         chunk_type: String,
+        /// remove the chunk even if that leaves the file structurally invalid, e.g. a palette
+        /// image without its PLTE chunk
+        #[arg(long)]
+        force: bool,
+        /// remove every chunk of the type rather than only the first
+        #[arg(long)]
+        all: bool,
+        /// remove the occurrence of the type at this 0-based position rather than the first
+        #[arg(long, value_name = "N", conflicts_with = "all")]
+        nth: Option<usize>,
+        #[command(flatten)]
+        batch: Batch,
+    },
+
+    /// Remove every ancillary chunk, such as text, timestamps and hidden messages, keeping only
+    /// the critical chunks that make up the image
+    Strip {
+        /// path to the PNG file
+        file: String,
+        /// save a stripped copy
+        output_file: Option<String>,
+        /// also keep chunks of this type, e.g. tRNS. Can be given more than once
+        #[arg(long, value_name = "TYPE")]
+        keep: Vec<String>,
+    },
+
+    /// Split IDAT chunks that are larger than a limit, or merge consecutive IDAT chunks into one
+    #[command(group(ArgGroup::new("mode").required(true).args(["max_idat", "merge_idat"])))]
+    Rechunk {
+        /// path to the PNG file
+        file: String,
+        /// largest number of data bytes to allow in one IDAT chunk
+        #[arg(long, value_name = "BYTES", value_parser = clap::value_parser!(u32).range(1..))]
+        max_idat: Option<u32>,
+        /// merge each run of consecutive IDAT chunks into a single chunk
+        #[arg(long)]
+        merge_idat: bool,
+        /// save a modified copy
+        output_file: Option<String>,
+    },
+
+    /// Rewrite a PNG file in canonical form without changing its image: chunks in the order the
+    /// specification describes, IDAT merged, empty and duplicate ancillary chunks dropped and
+    /// CRCs recalculated. Prints a summary of what changed
+    Canonicalize {
+        /// path to the PNG file
+        file: String,
+        /// save a modified copy
+        output_file: Option<String>,
+        #[command(flatten)]
+        steps: CanonicalizeSteps,
     },
 
     /// Print the contents of a PNG file
     Print {
+        /// path to the PNG file, or `-` to read it from stdin
+        file: String,
+        /// print a stable, line-oriented dump that can be diffed and read back with `import`
+        #[arg(long)]
+        canonical: bool,
+        /// accept chunks with an incorrect CRC or a non-standard signature, warning about each one.
+        /// The file is not modified
+        #[arg(long)]
+        fix_crc: bool,
+        /// follow each chunk with a short description of its type
+        #[arg(long, conflicts_with = "canonical")]
+        describe: bool,
+        /// list each PNG stream separately when more PNG files are appended after the first IEND
+        #[arg(long, conflicts_with = "canonical")]
+        all_streams: bool,
+        #[command(flatten)]
+        filter: ChunkFilter,
+    },
+
+    /// Check the signature, structure and CRCs of a PNG file and look for private chunks. Exits
+    /// with 0 if everything passed, 1 if the file couldn't be read, 2 if there were only warnings,
+    /// 3 if there were errors and 4 if the path is a directory
+    Audit {
+        /// path to the PNG file
+        file: String,
+    },
+
+    /// Show a summary of a PNG file: chunk count, size, dimensions, bit depth, colour type and
+    /// interlacing
+    Info {
+        /// path to the PNG file
+        file: String,
+        /// report the number of palette entries and how many of them have transparency
+        #[arg(long)]
+        palette: bool,
+        /// report the tool that wrote the file, from its Software text chunk
+        #[arg(long)]
+        producer: bool,
+    },
+
+    /// Repair a PNG file whose chunks have incorrect CRCs by recalculating them from each chunk's
+    /// type and data
+    Fix {
+        /// path to the PNG file
+        file: String,
+        /// save a repaired copy
+        output_file: Option<String>,
+    },
+
+    /// Check a PNG file's signature, chunk CRCs, critical chunk order and trailing data, printing
+    /// every problem found. Exits with a failure status if there are any
+    Verify {
+        /// path to the PNG file
+        file: String,
+        /// also check that ancillary chunks such as gAMA, tRNS and pHYs are placed where the
+        /// specification allows relative to PLTE and IDAT
+        #[arg(long)]
+        strict: bool,
+    },
+
+    /// Print one aligned line per chunk: index, type, data length, CRC in hex, byte offset in the
+    /// file and the flags of the chunk type
+    List {
         /// path to the PNG file
         file: String,
     },
+
+    /// Print a one-line summary of a PNG file: dimensions, colour type, chunk counts, size and
+    /// whether it is valid, or `invalid: <reason>`
+    Stat {
+        /// path to the PNG file
+        file: String,
+    },
+
+    /// Check a PNG file and print one line per issue as `<code> <offset> <message>`. The code is
+    /// stable, e.g. PNG001 for a missing IEND, and the offset is `-` when no single chunk is at
+    /// fault. Exits with 1 if there are any issues
+    Lint {
+        /// path to the PNG file
+        file: String,
+    },
+
+    /// Move messages from a private chunk type into standard tEXt chunks that image viewers show.
+    /// Each matching chunk is replaced in place
+    Migrate {
+        /// path to the PNG file
+        file: String,
+        /// type of the chunks holding the messages
+        chunk_type: String,
+        /// save a modified copy
+        output_file: Option<String>,
+        /// keyword to give the tEXt chunks
+        #[arg(long, default_value = "Comment")]
+        keyword: String,
+    },
+
+    /// Print the keyword and text of every tEXt, zTXt and iTXt chunk, one per line
+    Text {
+        /// path to the PNG file
+        file: String,
+    },
+
+    /// Edit the tEXt metadata of a PNG file
+    Meta {
+        #[command(subcommand)]
+        action: MetaAction,
+    },
+
+    /// Write the data of the first chunk of a given type, or of all of them, to a file
+    Extract {
+        /// path to the PNG file
+        file: String,
+        /// type of chunk to extract
+        chunk_type: String,
+        /// path to write the chunk data to
+        output_file: String,
+        /// inflate the compressed data of a zTXt, iTXt or iCCP chunk, leaving out the fields
+        /// before it
+        #[arg(long, conflicts_with_all = ["all", "raw"])]
+        decompress: bool,
+        /// write the whole chunk as it is in the file: length, type, data and CRC
+        #[arg(long)]
+        raw: bool,
+        /// extract the occurrence of the type at this 0-based position rather than the first
+        #[arg(long, value_name = "N", conflicts_with = "all")]
+        nth: Option<usize>,
+        /// extract every chunk of the type rather than the first. Requires --concat
+        #[arg(long, requires = "concat")]
+        all: bool,
+        /// with --all, write the data of the chunks one after another into the output file, e.g.
+        /// to reassemble split IDAT chunks
+        #[arg(long, requires = "all")]
+        concat: bool,
+    },
+
+    /// Write the ICC colour profile embedded in a PNG file's iCCP chunk to a file, printing the
+    /// profile name
+    Icc {
+        /// path to the PNG file
+        file: String,
+        /// path to write the ICC profile to, e.g. profile.icc
+        output_file: String,
+    },
+
+    /// Print a manifest line `<fingerprint>  <path>` for every PNG file under a directory. The
+    /// fingerprint covers only the critical chunks, so editing metadata doesn't change it
+    Manifest {
+        /// directory to search for PNG files
+        #[arg(required_unless_present = "check")]
+        dir: Option<String>,
+        /// check the files listed in a manifest instead, reporting those whose image changed
+        #[arg(long, value_name = "MANIFEST", conflicts_with = "dir")]
+        check: Option<String>,
+    },
+
+    /// Check that the data of the first chunk of a given type is identical to a file's contents.
+    /// Exits with 1 and the offset of the first differing byte if it isn't
+    VerifyData {
+        /// path to the PNG file
+        file: String,
+        /// type of chunk to check
+        chunk_type: String,
+        /// path to the file holding the expected data
+        expected_file: String,
+    },
+
+    /// Build a PNG file from a canonical dump produced by `print --canonical`
+    Import {
+        /// path to the canonical dump
+        file: String,
+        /// path to write the PNG file to
+        output_file: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MetaAction {
+    /// Set one tEXt entry per KEY=VALUE pair, updating the first tEXt chunk with the same keyword
+    /// in place or adding a new one before IEND
+    Set {
+        /// path to the PNG file
+        file: String,
+        /// entries to set, e.g. Title="My Image" Author=Me
+        #[arg(required = true, value_name = "KEY=VALUE", value_parser = parse_key_value)]
+        entries: Vec<(String, String)>,
+        /// save a modified copy here instead of modifying the file in place
+        #[arg(long)]
+        output_file: Option<String>,
+    },
+
+    /// Print the text of the first tEXt, zTXt or iTXt entry with a keyword
+    Get {
+        /// path to the PNG file
+        file: String,
+        /// keyword to look up, e.g. Title
+        keyword: String,
+    },
+
+    /// Remove every tEXt, zTXt and iTXt entry with a keyword. Does nothing if there are none
+    Unset {
+        /// path to the PNG file
+        file: String,
+        /// keyword to remove, e.g. Title
+        keyword: String,
+        /// save a modified copy here instead of modifying the file in place
+        #[arg(long)]
+        output_file: Option<String>,
+    },
+}
+
+/// Parses a `KEY=VALUE` pair, splitting at the first `=`
+fn parse_key_value(pair: &str) -> Result<(String, String), String> {
+    pair.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got {:?}", pair))
+}
+
+/// Parses the 4 bytes of a chunk type from 8 hex digits
+fn parse_type_bytes(hex: &str) -> Result<[u8; 4], String> {
+    encoding::hex_decode(hex)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| "expected 4 bytes as 8 hex digits".to_string())
+}
+
+/// Steps of `canonicalize` that can be turned off
+#[derive(Args, Debug, Default)]
+pub struct CanonicalizeSteps {
+    /// keep the chunks in their current order
+    #[arg(long)]
+    pub no_reorder: bool,
+    /// keep IDAT chunks as they are split
+    #[arg(long)]
+    pub no_merge_idat: bool,
+    /// keep ancillary chunks with no data
+    #[arg(long)]
+    pub no_drop_empty: bool,
+    /// keep ancillary chunks identical to an earlier chunk
+    #[arg(long)]
+    pub no_dedup: bool,
+    /// refuse files with incorrect CRCs rather than recalculating them
+    #[arg(long)]
+    pub no_fix_crc: bool,
+}
+
+/// Where `encode` takes the message from
+#[derive(Args, Debug)]
+pub struct MessageSource {
+    /// message to hide in the PNG file, or `-` to read all of stdin
+    #[arg(required_unless_present_any = ["message0", "input_file", "message_stdin"])]
+    pub message: Option<String>,
+    /// read the message from stdin up to the first NUL byte instead, e.g. from `printf '%s\0'`
+    #[arg(long, conflicts_with_all = ["message", "input_file", "message_stdin", "more_files"])]
+    pub message0: bool,
+    /// hide the raw bytes of this file instead
+    #[arg(
+        long,
+        value_name = "PATH",
+        visible_alias = "message-file",
+        conflicts_with = "message"
+    )]
+    pub input_file: Option<String>,
+    /// hide the raw bytes of all of stdin instead, the same as a message of `-`
+    #[arg(long, conflicts_with_all = ["message", "input_file", "more_files"])]
+    pub message_stdin: bool,
+    /// drop one newline from the end of a message read from stdin, e.g. from `echo`
+    #[arg(long)]
+    pub strip_trailing_newline: bool,
+}
+
+/// Further files for `encode` and `remove` to change the same way
+#[derive(Args, Debug, Default)]
+pub struct Batch {
+    /// more PNG files to change the same way. Failures don't stop the others and are listed
+    /// together at the end
+    #[arg(long = "also", value_name = "PATH", num_args = 1..)]
+    pub more_files: Vec<String>,
+    /// write each changed file into this directory under its own name instead of replacing it
+    #[arg(long, value_name = "DIR")]
+    pub output_dir: Option<String>,
+}
+
+/// Where `encode` writes the modified PNG. By default the file is replaced in place
+#[derive(Args, Debug, Default)]
+pub struct EncodeDestination {
+    /// save a modified copy, or write it to stdout for `-`
+    #[arg(
+        short,
+        long = "output",
+        value_name = "PATH",
+        conflicts_with_all = ["more_files", "output_dir"]
+    )]
+    pub output_file: Option<String>,
+    /// write the modified PNG to stdout instead, e.g. for piping
+    #[arg(long, conflicts_with_all = ["output_file", "more_files", "output_dir"])]
+    pub stdout: bool,
+}
+
+/// Where `encode` puts the new chunk. By default it goes right before IEND
+#[derive(Args, Debug, Default)]
+pub struct ChunkPlacement {
+    /// if the file already has a chunk of the type, replace the data of the first one in place
+    /// rather than adding another chunk
+    #[arg(long)]
+    pub replace: bool,
+    /// add the chunk after IEND at the very end of the file, which some readers reject
+    #[arg(long, conflicts_with_all = ["after", "index"])]
+    pub append: bool,
+    /// add the chunk right after the first chunk of this type, e.g. IHDR
+    #[arg(long, value_name = "TYPE", conflicts_with = "index")]
+    pub after: Option<String>,
+    /// add the chunk so it becomes the chunk at this index, counting from 0. It must land after
+    /// IHDR and before IEND
+    #[arg(long, value_name = "N")]
+    pub index: Option<usize>,
+}
+
+/// How `encode` frames the message inside the chunk data
+#[derive(Args, Debug, Default)]
+pub struct EncodeFraming {
+    /// follow the message with a SHA-256 hash of it, checked by `decode --verify-hash`
+    #[arg(long)]
+    pub with_hash: bool,
+    /// store the message with its length and random padding so the chunk data is always this
+    /// many bytes, hiding the message length. Read it back with `decode --padded`
+    #[arg(long, value_name = "BYTES")]
+    pub pad_to: Option<u32>,
+    /// seed for the random padding, so the same message and seed give identical output
+    #[arg(long, requires = "pad_to")]
+    pub seed: Option<u64>,
+}
+
+/// How `decode` shows the message
+#[derive(Args, Debug, Default)]
+pub struct DecodeOutput {
+    /// only decode the first chunk of the type rather than every one in file order
+    #[arg(long)]
+    pub first: bool,
+    /// print at most this many messages, followed by a note of how many were left out
+    #[arg(long, value_name = "N", conflicts_with_all = ["first", "output"])]
+    pub max_messages: Option<usize>,
+    /// comma separated interpretations of the chunk data to try in order, printing the first
+    /// that produces readable text
+    #[arg(long = "try", value_delimiter = ',')]
+    pub interpretations: Vec<Interpretation>,
+    /// only print the length in bytes of the chunk data
+    #[arg(long)]
+    pub length_only: bool,
+    /// write the message bytes to this file, or to stdout for `-`, as they are instead of
+    /// printing them, for messages that aren't text. Messages from several chunks are written one
+    /// after another
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["interpretations", "length_only"])]
+    pub output: Option<String>,
+    /// prefix each message with the 0-based occurrence index of its chunk, as taken by
+    /// `remove --nth`, showing data that isn't UTF-8 as a hex preview
+    #[arg(long, conflicts_with_all = ["first", "interpretations", "length_only", "output"])]
+    pub all: bool,
+    /// print messages that aren't valid UTF-8 as hex rather than as text with the invalid bytes
+    /// replaced
+    #[arg(long, conflicts_with_all = ["interpretations", "length_only", "output", "all"])]
+    pub hex: bool,
+}
+
+/// How `encode` framed the message inside the chunk data
+#[derive(Args, Debug, Default)]
+pub struct DecodeFraming {
+    /// strip the padding added by `encode --pad-to`
+    #[arg(long, conflicts_with = "length_only")]
+    pub padded: bool,
+    /// check the hash added by `encode --with-hash`, warning if it doesn't match the message
+    #[arg(long, conflicts_with = "length_only")]
+    pub verify_hash: bool,
+}
+
+/// Options limiting which chunks are shown
+#[derive(Args, Debug, Default)]
+pub struct ChunkFilter {
+    /// hide chunks of this type. Can be given more than once
+    #[arg(long, value_name = "TYPE")]
+    pub exclude: Vec<String>,
+    /// only show chunks with at least this many bytes of data
+    #[arg(long, value_name = "BYTES")]
+    pub min_size: Option<u32>,
+    /// only show chunks with at most this many bytes of data
+    #[arg(long, value_name = "BYTES")]
+    pub max_size: Option<u32>,
+}
+
+impl ChunkFilter {
+    pub fn matches(&self, chunk: &Chunk) -> bool {
+        let chunk_type = chunk.chunk_type().to_string();
+        !self.exclude.contains(&chunk_type)
+            && self.min_size.is_none_or(|min| chunk.length() >= min)
+            && self.max_size.is_none_or(|max| chunk.length() <= max)
+    }
+}
+
+/// What to do with files that have no tIME chunk when filtering by time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum NoTimePolicy {
+    /// Process them as if they were recent
+    #[default]
+    Process,
+    /// Leave them unchanged
+    Skip,
+}
+
+/// Options limiting which files are processed, by the time in their tIME chunk
+#[derive(Args, Debug, Default)]
+pub struct TimeFilter {
+    /// only process the file if its tIME chunk is later than the start of this date, given as
+    /// YYYY-MM-DD
+    #[arg(long, value_name = "DATE")]
+    pub since: Option<Timestamp>,
+    /// whether to process files without a tIME chunk when --since is given
+    #[arg(long, value_enum, default_value_t, requires = "since")]
+    pub no_time_policy: NoTimePolicy,
+}
+
+impl MessageSource {
+    /// Whether the message is read from stdin
+    pub fn reads_stdin(&self) -> bool {
+        self.message_stdin
+            || self.message0
+            || self.message.as_deref() == Some("-")
+            || (self.message.is_none() && self.input_file.is_none())
+    }
+}
+
+impl TimeFilter {
+    /// Whether the file's tIME chunk is newer than `since`, or `no_time_policy` lets it through
+    pub fn allows(&self, png: &Png) -> Result<bool, TimestampError> {
+        let Some(since) = self.since else {
+            return Ok(true);
+        };
+        match png.chunk_by_type("tIME") {
+            Some(chunk) => Ok(Timestamp::try_from(chunk.data())? > since),
+            None => Ok(self.no_time_policy == NoTimePolicy::Process),
+        }
+    }
 }
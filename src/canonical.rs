@@ -0,0 +1,152 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::encoding;
+use crate::png::Png;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// First line of every canonical dump, identifying the format and its version
+const FORMAT_LINE: &str = "pngme-canonical 1";
+
+/// Placeholder written in place of the data of an empty chunk
+const NO_DATA: &str = "-";
+
+#[derive(Error, Debug)]
+pub enum CanonicalError {
+    #[error("missing '{FORMAT_LINE}' format line")]
+    MissingFormatLine(),
+
+    #[error("line {line}: {reason}")]
+    InvalidLine { line: usize, reason: String },
+}
+
+/// Renders chunks as line-oriented text, one chunk per line in file order:
+///
+/// `<type> <data length> <crc as 8 hex digits> <data as base64, or - when empty>`
+///
+/// The output is stable for identical files, so it can be committed and diffed, and it is lossless
+/// so `from_canonical` can rebuild the PNG from it
+pub fn to_canonical<'a>(chunks: impl IntoIterator<Item = &'a Chunk>) -> String {
+    let mut text = format!("{}\n", FORMAT_LINE);
+    for chunk in chunks {
+        let data = match chunk.data() {
+            [] => NO_DATA.to_string(),
+            data => encoding::base64_encode(data),
+        };
+        text.push_str(&format!(
+            "{} {} {:08x} {}\n",
+            chunk.chunk_type(),
+            chunk.length(),
+            chunk.crc(),
+            data
+        ));
+    }
+    text
+}
+
+/// Rebuilds a PNG from the output of `to_canonical`, checking each line's length and CRC against
+/// its data
+pub fn from_canonical(text: &str) -> Result<Png, CanonicalError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty());
+
+    match lines.next() {
+        Some((_, line)) if line.trim() == FORMAT_LINE => (),
+        _ => return Err(CanonicalError::MissingFormatLine()),
+    }
+
+    let chunks = lines
+        .map(|(i, line)| {
+            parse_line(line).map_err(|reason| CanonicalError::InvalidLine {
+                line: i + 1,
+                reason,
+            })
+        })
+        .collect::<Result<Vec<Chunk>, CanonicalError>>()?;
+
+    Ok(Png::from_chunks(chunks))
+}
+
+fn parse_line(line: &str) -> Result<Chunk, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [chunk_type, length, crc, data] = fields[..] else {
+        return Err(format!("expected 4 fields, found {}", fields.len()));
+    };
+
+    let chunk_type = ChunkType::from_str(chunk_type).map_err(|e| e.to_string())?;
+    let length: u32 = length
+        .parse()
+        .map_err(|_| format!("invalid length '{}'", length))?;
+    let crc = u32::from_str_radix(crc, 16).map_err(|_| format!("invalid CRC '{}'", crc))?;
+    let data = match data {
+        NO_DATA => vec![],
+        data => encoding::base64_decode(data).ok_or("invalid base64 data")?,
+    };
+
+    let chunk = Chunk::new(chunk_type, data);
+    if chunk.length() != length {
+        return Err(format!(
+            "length is {} but the data is {} bytes",
+            length,
+            chunk.length()
+        ));
+    }
+    if chunk.crc() != crc {
+        return Err(format!(
+            "CRC is {:08x} but the data gives {:08x}",
+            crc,
+            chunk.crc()
+        ));
+    }
+
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        let mut png = Png::default();
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            b"hidden message".to_vec(),
+        ));
+        png
+    }
+
+    #[test]
+    fn test_canonical_round_trip() {
+        let png = testing_png();
+        let text = to_canonical(png.chunks());
+        let imported = from_canonical(&text).unwrap();
+        assert_eq!(imported.as_bytes(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_canonical_format() {
+        let text = to_canonical(testing_png().chunks());
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], FORMAT_LINE);
+        assert!(lines[1].starts_with("IHDR 13 "));
+        assert_eq!(lines[3], "IEND 0 ae426082 -");
+        assert!(lines[4].ends_with(" aGlkZGVuIG1lc3NhZ2U="));
+    }
+
+    #[test]
+    fn test_canonical_rejects_bad_crc() {
+        let text = format!("{}\nruSt 2 00000000 aGk=\n", FORMAT_LINE);
+        assert!(matches!(
+            from_canonical(&text),
+            Err(CanonicalError::InvalidLine { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_canonical_requires_format_line() {
+        assert!(from_canonical("IEND 0 ae426082 -\n").is_err());
+    }
+}
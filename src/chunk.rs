@@ -36,52 +36,18 @@ impl TryFrom<&[u8]> for Chunk {
     type Error = ChunkError;
 
     fn try_from(value: &[u8]) -> Result<Self, ChunkError> {
-        if value.len() < 12 {
-            return Err(ChunkError::InvalidLength(12));
-        }
-
-        let mut iter = value.iter();
-
-        // First 4 bytes into length
-        let length_field = iter.by_ref().take(4).cloned().collect::<Vec<u8>>();
-        let length = u32::from_be_bytes(length_field.try_into().expect("Invalid length"));
-
-        // Next 4 bytes into chunk_type
-        let chunk_type_field: [u8; 4] = iter
-            .by_ref()
-            .take(4)
-            .cloned()
-            .collect::<Vec<u8>>()
-            .try_into()
-            .expect("Invalid chunk type");
-        let chunk_type = ChunkType::try_from(chunk_type_field).expect("Invalid chunk type");
-
-        // All other bytes besides the last 4 into chunk_data
-        let chunk_data: Vec<u8> = iter.by_ref().take(value.len() - 12).cloned().collect();
-
-        // Calculate the CRC from the chunk type and data bytes
-        let crc_bytes = iter.take(4).cloned().collect::<Vec<u8>>();
-        let supplied_crc = u32::from_be_bytes(crc_bytes.try_into().expect("Invalid CRC"));
+        let chunk = Chunk::try_from_lenient(value)?;
 
         // Check the supplied CRC value is correct
-        let mut type_and_data_bytes =
-            Vec::with_capacity(chunk_type.bytes().len() + chunk_data.len());
-        type_and_data_bytes.extend_from_slice(&chunk_type.bytes());
-        type_and_data_bytes.extend(&chunk_data);
-        let real_crc = Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(&type_and_data_bytes);
-        if supplied_crc != real_crc {
+        let real_crc = chunk.calculated_crc();
+        if chunk.crc != real_crc {
             return Err(ChunkError::InvalidCrc {
-                got: supplied_crc,
+                got: chunk.crc,
                 expected: real_crc,
             });
         }
 
-        Ok(Chunk {
-            length,
-            chunk_type,
-            chunk_data,
-            crc: supplied_crc,
-        })
+        Ok(chunk)
     }
 }
 
@@ -95,21 +61,56 @@ impl std::fmt::Display for Chunk {
     }
 }
 
+/// Calculate the CRC from the chunk type and data bytes
+fn calculate_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    let mut type_and_data_bytes = Vec::with_capacity(chunk_type.bytes().len() + data.len());
+    type_and_data_bytes.extend_from_slice(&chunk_type.bytes());
+    type_and_data_bytes.extend(data);
+    Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(&type_and_data_bytes)
+}
+
 #[allow(dead_code)]
 impl Chunk {
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
-        let mut type_and_data_bytes = Vec::with_capacity(chunk_type.bytes().len() + data.len());
-        type_and_data_bytes.extend_from_slice(&chunk_type.bytes());
-        type_and_data_bytes.extend(&data);
-
         Chunk {
             length: data.len() as u32,
+            crc: calculate_crc(&chunk_type, &data),
             chunk_type,
-            chunk_data: data.clone(),
-            crc: Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(&type_and_data_bytes),
+            chunk_data: data,
         }
     }
 
+    /// Parses a chunk like `try_from`, but keeps the supplied CRC even if it is incorrect
+    pub fn try_from_lenient(value: &[u8]) -> Result<Chunk, ChunkError> {
+        if value.len() < 12 {
+            return Err(ChunkError::InvalidLength(12));
+        }
+
+        let (length_field, rest) = value.split_at(4);
+        let (chunk_type_field, rest) = rest.split_at(4);
+        let length = u32::from_be_bytes(length_field.try_into().unwrap());
+
+        // Bytes after the CRC belong to whatever follows the chunk and are ignored
+        let needed = 12 + length as u64;
+        if (value.len() as u64) < needed {
+            return Err(ChunkError::InvalidLength(needed));
+        }
+        let (chunk_data, rest) = rest.split_at(length as usize);
+        let crc_field = &rest[..4];
+
+        let chunk_type_bytes: [u8; 4] = chunk_type_field.try_into().unwrap();
+        let chunk_type = ChunkType::from_bytes_lenient(chunk_type_bytes);
+        let chunk_data = chunk_data.to_vec();
+        let crc = u32::from_be_bytes(crc_field.try_into().unwrap());
+
+        Ok(Chunk {
+            length,
+            chunk_type,
+            chunk_data,
+            crc,
+        })
+    }
+
     pub fn length(&self) -> u32 {
         self.length
     }
@@ -126,6 +127,27 @@ impl Chunk {
         self.crc
     }
 
+    /// The CRC that the chunk type and data should have
+    pub fn calculated_crc(&self) -> u32 {
+        calculate_crc(&self.chunk_type, &self.chunk_data)
+    }
+
+    /// Whether the supplied CRC matches the chunk type and data
+    pub fn is_crc_valid(&self) -> bool {
+        self.crc == self.calculated_crc()
+    }
+
+    /// Replaces the supplied CRC with the one calculated from the chunk type and data
+    pub fn recalculate_crc(&mut self) {
+        self.crc = self.calculated_crc();
+    }
+
+    /// Splits the data at the first occurrence of `byte`, leaving the separator out of both halves
+    pub fn data_split_first(&self, byte: u8) -> Option<(&[u8], &[u8])> {
+        let index = self.chunk_data.iter().position(|&b| b == byte)?;
+        Some((&self.chunk_data[..index], &self.chunk_data[index + 1..]))
+    }
+
     pub fn data_as_string(&self) -> Result<String, string::FromUtf8Error> {
         String::from_utf8(self.chunk_data.clone())
     }
@@ -250,6 +272,89 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_lenient_chunk_keeps_invalid_crc() {
+        let data_length: u32 = 42;
+        let chunk_type = "RuSt".as_bytes();
+        let message_bytes = "This is where your secret message will be!".as_bytes();
+        let crc: u32 = 2882656333;
+
+        let chunk_data: Vec<u8> = data_length
+            .to_be_bytes()
+            .iter()
+            .chain(chunk_type.iter())
+            .chain(message_bytes.iter())
+            .chain(crc.to_be_bytes().iter())
+            .copied()
+            .collect();
+
+        let chunk = Chunk::try_from_lenient(chunk_data.as_ref()).unwrap();
+
+        assert_eq!(chunk.crc(), 2882656333);
+        assert_eq!(chunk.calculated_crc(), 2882656334);
+        assert!(!chunk.is_crc_valid());
+        assert!(testing_chunk().is_crc_valid());
+    }
+
+    #[test]
+    fn test_chunk_type_not_letters() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes[6] = b'1';
+        let mut chunk = Chunk::try_from_lenient(bytes.as_ref()).unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "Ru1t");
+
+        // Files from other tools may use such types, so parsing only checks the CRC
+        chunk.recalculate_crc();
+        let chunk = Chunk::try_from(chunk.as_bytes().as_ref()).unwrap();
+        assert!(!chunk.chunk_type().is_valid());
+    }
+
+    #[test]
+    fn test_recalculate_crc() {
+        let mut bytes = testing_chunk().as_bytes();
+        let crc_start = bytes.len() - 4;
+        bytes[crc_start] ^= 0xff;
+
+        let mut chunk = Chunk::try_from_lenient(bytes.as_ref()).unwrap();
+        chunk.recalculate_crc();
+        assert_eq!(chunk.crc(), 2882656334);
+        assert_eq!(chunk.as_bytes(), testing_chunk().as_bytes());
+    }
+
+    #[test]
+    fn test_truncated_chunk_is_error() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes.truncate(30);
+        assert!(matches!(
+            Chunk::try_from(bytes.as_ref()),
+            Err(ChunkError::InvalidLength(54))
+        ));
+        assert!(Chunk::try_from_lenient(&bytes[..11]).is_err());
+    }
+
+    #[test]
+    fn test_chunk_from_concatenated_bytes() {
+        let second = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"more".to_vec());
+        let mut bytes = testing_chunk().as_bytes();
+        bytes.extend(second.as_bytes());
+
+        let chunk = Chunk::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.chunk_type().to_string(), "RuSt");
+        assert_eq!(chunk.crc(), 2882656334);
+    }
+
+    #[test]
+    fn test_data_split_first() {
+        let chunk_type = ChunkType::from_str("tEXt").unwrap();
+        let chunk = Chunk::new(chunk_type, b"Title\0A\0picture".to_vec());
+        assert_eq!(
+            chunk.data_split_first(0),
+            Some((&b"Title"[..], &b"A\0picture"[..]))
+        );
+        assert_eq!(chunk.data_split_first(b'#'), None);
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
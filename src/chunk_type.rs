@@ -10,9 +10,31 @@ pub enum ChunkTypeError {
     NotAlpabetical,
 }
 
+/// Chunk types registered by the PNG Specification v1.2, with a short description of each
+pub const REGISTERED_TYPES: [(&str, &str); 18] = [
+    ("IHDR", "image header"),
+    ("PLTE", "palette"),
+    ("IDAT", "image data"),
+    ("IEND", "image trailer"),
+    ("cHRM", "primary chromaticities"),
+    ("gAMA", "image gamma"),
+    ("iCCP", "embedded ICC profile"),
+    ("sBIT", "significant bits"),
+    ("sRGB", "standard RGB colour space"),
+    ("bKGD", "background colour"),
+    ("hIST", "palette histogram"),
+    ("tRNS", "transparency"),
+    ("pHYs", "physical pixel dimensions"),
+    ("sPLT", "suggested palette"),
+    ("tIME", "last-modification time"),
+    ("iTXt", "international textual data"),
+    ("tEXt", "textual data"),
+    ("zTXt", "compressed textual data"),
+];
+
 /// PNG chunk types as defined by PNG Specification v1.2:
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ChunkType {
     /// A 4-byte chunk type code. For convenience in description and in examining PNG files, type
     /// codes are restricted to consist of uppercase and lowercase ASCII letters.
@@ -23,6 +45,10 @@ impl TryFrom<[u8; 4]> for ChunkType {
     type Error = ChunkTypeError;
 
     fn try_from(bytes: [u8; 4]) -> Result<Self, ChunkTypeError> {
+        if !bytes.iter().all(u8::is_ascii_alphabetic) {
+            return Err(ChunkTypeError::NotAlpabetical);
+        }
+
         Ok(ChunkType { bytes })
     }
 }
@@ -61,12 +87,18 @@ impl std::fmt::Display for ChunkType {
 
 #[allow(dead_code)]
 impl ChunkType {
+    /// Construct a ChunkType from any 4 bytes, even ones that aren't letters, for reading damaged
+    /// or non-conforming files. `is_valid` is false for such types
+    pub fn from_bytes_lenient(bytes: [u8; 4]) -> ChunkType {
+        ChunkType { bytes }
+    }
+
     pub fn bytes(&self) -> [u8; 4] {
         self.bytes
     }
 
     pub fn is_valid(&self) -> bool {
-        self.is_reserved_bit_valid()
+        self.bytes.iter().all(u8::is_ascii_alphabetic) && self.is_reserved_bit_valid()
     }
 
     pub fn is_critical(&self) -> bool {
@@ -98,6 +130,26 @@ impl ChunkType {
         self.bytes[2] & mask == 0
     }
 
+    /// Returns the registered type this one differs from only by letter case, if any. Such types
+    /// carry different property bits to the registered chunk but are easily mistaken for it
+    pub fn shadowed_registered_type(&self) -> Option<&'static str> {
+        REGISTERED_TYPES
+            .into_iter()
+            .map(|(registered, _)| registered)
+            .find(|registered| {
+                registered.as_bytes().eq_ignore_ascii_case(&self.bytes)
+                    && registered.as_bytes() != self.bytes
+            })
+    }
+
+    /// Short description of a registered chunk type
+    pub fn description(&self) -> Option<&'static str> {
+        REGISTERED_TYPES
+            .into_iter()
+            .find(|(registered, _)| registered.as_bytes() == self.bytes)
+            .map(|(_, description)| description)
+    }
+
     pub fn is_safe_to_copy(&self) -> bool {
         // Bit 5 of fourth byte
 
@@ -123,6 +175,27 @@ mod tests {
         assert_eq!(expected, actual.bytes());
     }
 
+    #[test]
+    pub fn test_chunk_type_from_bytes_not_alphabetic() {
+        for bytes in [
+            [0x31, 117, 83, 116],
+            [82, 117, 83, b' '],
+            [0xde, 0xad, 0xbe, 0xef],
+        ] {
+            assert!(matches!(
+                ChunkType::try_from(bytes),
+                Err(ChunkTypeError::NotAlpabetical)
+            ));
+        }
+    }
+
+    #[test]
+    pub fn test_chunk_type_from_bytes_lenient() {
+        let chunk = ChunkType::from_bytes_lenient([0x31, 117, 83, 116]);
+        assert_eq!(chunk.bytes(), [0x31, 117, 83, 116]);
+        assert!(!chunk.is_valid());
+    }
+
     #[test]
     pub fn test_chunk_type_from_str() {
         let expected = ChunkType::try_from([82, 117, 83, 116]).unwrap();
@@ -193,6 +266,33 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_shadows_registered_type() {
+        let chunk = ChunkType::from_str("tExt").unwrap();
+        assert_eq!(chunk.shadowed_registered_type(), Some("tEXt"));
+
+        let chunk = ChunkType::from_str("gama").unwrap();
+        assert_eq!(chunk.shadowed_registered_type(), Some("gAMA"));
+    }
+
+    #[test]
+    pub fn test_chunk_type_does_not_shadow_registered_type() {
+        let chunk = ChunkType::from_str("tEXt").unwrap();
+        assert_eq!(chunk.shadowed_registered_type(), None);
+
+        let chunk = ChunkType::from_str("RuSt").unwrap();
+        assert_eq!(chunk.shadowed_registered_type(), None);
+    }
+
+    #[test]
+    pub fn test_chunk_type_description() {
+        let chunk = ChunkType::from_str("IHDR").unwrap();
+        assert_eq!(chunk.description(), Some("image header"));
+
+        let chunk = ChunkType::from_str("RuSt").unwrap();
+        assert_eq!(chunk.description(), None);
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -1,54 +1,1704 @@
+use crate::args::{
+    CanonicalizeSteps, ChunkFilter, ChunkPlacement, DecodeFraming, DecodeOutput, EncodeDestination,
+    EncodeFraming, MessageSource, TimeFilter,
+};
+use crate::canonical;
 use crate::chunk::Chunk;
 use crate::chunk_type::ChunkType;
-use crate::png::Png;
+use crate::encoding;
+use crate::ihdr::IhdrData;
+use crate::palette;
+use crate::payload;
+use crate::png::{Png, PngError};
+use crate::rng::SplitMix64;
+use crate::stdio;
+use crate::stego;
+use crate::text;
+use crate::validate::{self, Severity, ValidationIssue};
+use crate::zlib;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt;
 use std::fs;
-use std::str::FromStr;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::{self, FromStr};
+use thiserror::Error;
 
-/// Encodes a message into a PNG file
+#[derive(Error, Debug)]
+pub enum CommandError {
+    #[error("{0} is a directory, expected a file")]
+    IsDirectory(String),
+
+    #[error("message is {0} bytes but a chunk can hold at most {max} bytes", max = u32::MAX)]
+    MessageTooLong(usize),
+
+    #[error("audit found {0} warning(s)")]
+    AuditWarnings(usize),
+
+    #[error("audit found {0} error(s)")]
+    AuditErrors(usize),
+
+    #[error("lint found {0} issue(s)")]
+    LintFindings(usize),
+
+    #[error("verify found {0} issue(s)")]
+    VerifyFindings(usize),
+
+    #[error(
+        "removing {chunk_type} would break the file ({issues}), use --force to remove it anyway"
+    )]
+    RemovalBreaksStructure { chunk_type: String, issues: String },
+
+    #[error("{0} file(s) in the manifest changed or could not be read")]
+    ManifestMismatch(usize),
+
+    #[error("chunk data differs from {file} at byte {offset}")]
+    DataMismatch { file: String, offset: usize },
+
+    #[error("{0} chunks already hold text, only other chunk types can be migrated")]
+    AlreadyText(String),
+
+    #[error("the PNG file and the message can't both be read from stdin")]
+    StdinUsedTwice(),
+
+    #[error("a message from stdin can only be hidden in one file, use --input-file with --also")]
+    StdinForSeveralFiles(),
+
+    #[error("{0} is already the output of another file with the same name")]
+    OutputPathTaken(String),
+
+    #[error("{succeeded} succeeded, {failed} failed: {failures}")]
+    SomeFilesFailed {
+        succeeded: usize,
+        failed: usize,
+        failures: String,
+    },
+}
+
+impl CommandError {
+    /// Exit code for the process, distinct from the generic failure code of 1
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            CommandError::IsDirectory(_) => ExitCode::from(4),
+            CommandError::MessageTooLong(_) => ExitCode::FAILURE,
+            CommandError::AuditWarnings(_) => ExitCode::from(2),
+            CommandError::AuditErrors(_) => ExitCode::from(3),
+            CommandError::LintFindings(_) => ExitCode::FAILURE,
+            CommandError::VerifyFindings(_) => ExitCode::FAILURE,
+            CommandError::RemovalBreaksStructure { .. } => ExitCode::FAILURE,
+            CommandError::ManifestMismatch(_) => ExitCode::FAILURE,
+            CommandError::DataMismatch { .. } => ExitCode::FAILURE,
+            CommandError::AlreadyText(_) => ExitCode::FAILURE,
+            CommandError::StdinUsedTwice() => ExitCode::FAILURE,
+            CommandError::StdinForSeveralFiles() => ExitCode::FAILURE,
+            CommandError::OutputPathTaken(_) => ExitCode::FAILURE,
+            CommandError::SomeFilesFailed { .. } => ExitCode::FAILURE,
+        }
+    }
+}
+
+/// Runs `command` on `file` and then on each of `more_files`, carrying on past failures. With more
+/// than one file, a summary is printed and any failures are returned together as one error
+pub fn for_each_file(
+    file: &str,
+    more_files: &[String],
+    mut command: impl FnMut(&str) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if more_files.is_empty() {
+        return command(file);
+    }
+
+    let mut succeeded = 0;
+    let mut failures = vec![];
+    for file in std::iter::once(file).chain(more_files.iter().map(String::as_str)) {
+        match command(file) {
+            Ok(()) => succeeded += 1,
+            Err(e) => failures.push(format!("{}: {}", file, e)),
+        }
+    }
+    if !failures.is_empty() {
+        return Err(CommandError::SomeFilesFailed {
+            succeeded,
+            failed: failures.len(),
+            failures: failures.join("; "),
+        }
+        .into());
+    }
+    eprintln!("{} succeeded, 0 failed", succeeded);
+    Ok(())
+}
+
+/// Path for the copy of `file` written into `dir` by `--output-dir`, under the same file name.
+/// Fails if an earlier file with the same name, as recorded in `taken`, already went there
+pub fn path_in_dir(
+    dir: &str,
+    file: &str,
+    taken: &mut HashSet<String>,
+) -> Result<String, CommandError> {
+    let name = Path::new(file).file_name().unwrap_or_default();
+    let path = Path::new(dir).join(name).to_string_lossy().into_owned();
+    if !taken.insert(path.clone()) {
+        return Err(CommandError::OutputPathTaken(path));
+    }
+    Ok(path)
+}
+
+/// Fails if a message read from stdin would have to go into more than one file
+pub fn check_batch_source(
+    source: &MessageSource,
+    more_files: &[String],
+) -> Result<(), CommandError> {
+    if !more_files.is_empty() && source.reads_stdin() {
+        return Err(CommandError::StdinForSeveralFiles());
+    }
+    Ok(())
+}
+
+/// Encodes a message into a PNG file. The message is taken from `source`: the command line, all of
+/// stdin for `-`, a file read verbatim, or stdin up to the first NUL byte. The chunk goes right
+/// before IEND unless `placement` says otherwise or asks to replace an existing one. `framing` can
+/// add a hash of the message and pad the chunk data to a fixed size. Files that `time_filter`
+/// doesn't allow are left unchanged with a note. The file is replaced in place unless
+/// `destination` names somewhere else. A file of `-` is read from stdin and written to stdout
 pub fn encode(
     file: &str,
     chunk_type: &str,
+    source: &MessageSource,
+    placement: &ChunkPlacement,
+    framing: &EncodeFraming,
+    time_filter: &TimeFilter,
+    destination: &EncodeDestination,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let read_all_stdin = source.message_stdin || source.message.as_deref() == Some("-");
+    if stdio::is_stdio(file) && source.reads_stdin() {
+        return Err(CommandError::StdinUsedTwice().into());
+    }
+
+    let mut png = read_png(file)?;
+    if !time_filter.allows(&png)? {
+        eprintln!("Skipping {}: not modified since the --since date", file);
+        // A pipeline still gets the PNG, unchanged
+        let to_stdout = destination.stdout
+            || stdio::is_stdio(destination.output_file.as_deref().unwrap_or(file));
+        if to_stdout {
+            stdio::write_output(stdio::STDIO_PATH, &png.as_bytes())?;
+        }
+        return Ok(());
+    }
+
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    if let Some(warning) = shadowing_warning(&chunk_type) {
+        eprintln!("{}", warning);
+    }
+
+    let message = match (&source.message, &source.input_file) {
+        _ if read_all_stdin => {
+            let mut message = Vec::new();
+            io::stdin().lock().read_to_end(&mut message)?;
+            if source.strip_trailing_newline {
+                strip_trailing_newline(&mut message);
+            }
+            message
+        }
+        (Some(message), _) => message.as_bytes().to_vec(),
+        (None, Some(path)) => read_file(path)?,
+        (None, None) => read_until_nul(io::stdin().lock())?,
+    };
+    let message = frame_message(message, framing)?;
+    check_message_length(message.len())?;
+    let type_name = chunk_type.to_string();
+    let existing = png.chunks_by_type(&type_name).count();
+    let chunk = Chunk::new(chunk_type, message);
+    if existing > 0 && placement.replace {
+        png.replace_first_chunk(&type_name, chunk)?;
+    } else {
+        if existing > 0 {
+            eprintln!(
+                "Warning: the file already has {} {} chunk(s), use --replace to update the first \
+                 one instead of adding another",
+                existing, type_name
+            );
+        }
+        insert_chunk(&mut png, chunk, placement)?;
+    }
+
+    if destination.stdout {
+        io::stdout().lock().write_all(&png.as_bytes())?;
+    } else if let Some(path) = &destination.output_file {
+        stdio::write_output(path, &png.as_bytes())?;
+    } else {
+        write_in_place(file, &png.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Adds a new chunk where `placement` says, right before IEND by default
+fn insert_chunk(png: &mut Png, chunk: Chunk, placement: &ChunkPlacement) -> Result<(), PngError> {
+    if placement.append {
+        png.append_chunk(chunk);
+    } else if let Some(after) = &placement.after {
+        let index = png
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == *after)
+            .ok_or(PngError::ChunkNotFound())?;
+        png.insert_chunk(index + 1, chunk)?;
+    } else if let Some(index) = placement.index {
+        png.insert_chunk(index, chunk)?;
+    } else {
+        png.insert_chunk_before_iend(chunk);
+    }
+    Ok(())
+}
+
+/// Replaces a file's contents by writing a temporary file next to it and renaming it over the
+/// original, so the original is never left half written. A symlink is followed so the file it
+/// points to is replaced, keeping its permissions. For `-` the bytes go to stdout
+fn write_in_place(file: &str, bytes: &[u8]) -> io::Result<()> {
+    if stdio::is_stdio(file) {
+        return stdio::write_output(file, bytes);
+    }
+
+    let path = fs::canonicalize(file)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.pngme-tmp", name));
+    fs::write(&temp, bytes)?;
+    let permissions = fs::metadata(&path)?.permissions();
+    fs::set_permissions(&temp, permissions)
+        .and_then(|_| fs::rename(&temp, &path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })
+}
+
+/// Removes one `\n` or `\r\n` from the end of a message
+fn strip_trailing_newline(message: &mut Vec<u8>) {
+    if message.last() == Some(&b'\n') {
+        message.pop();
+        if message.last() == Some(&b'\r') {
+            message.pop();
+        }
+    }
+}
+
+/// Adds the hash and padding requested by `framing` to a message
+fn frame_message(
+    message: Vec<u8>,
+    framing: &EncodeFraming,
+) -> Result<Vec<u8>, payload::PayloadError> {
+    let message = if framing.with_hash {
+        payload::append_hash(&message)
+    } else {
+        message
+    };
+
+    match framing.pad_to {
+        Some(size) => {
+            let mut rng = match framing.seed {
+                Some(seed) => SplitMix64::new(seed),
+                None => SplitMix64::from_entropy(),
+            };
+            payload::pad(&message, size as usize, &mut rng)
+        }
+        None => Ok(message),
+    }
+}
+
+/// Decode prints the data within every occurrance of a given chunk type in file order, or only
+/// the first with `output.first` or up to `output.max_messages`. With `type_bytes`, chunks with
+/// exactly those type bytes are used instead. `output` picks how the messages are shown: the first
+/// of any given interpretations that yields readable text, only their lengths, their bytes
+/// written one after another to a file, or numbered by occurrence with data that isn't UTF-8 as a
+/// hex preview. Otherwise data that isn't UTF-8 is printed lossily with a warning, or as hex with
+/// `output.hex`. `framing` undoes the padding and hash added by `encode`
+pub fn decode(
+    file: &str,
+    chunk_type: &Option<String>,
+    type_bytes: Option<[u8; 4]>,
+    output: &DecodeOutput,
+    fix_crc: bool,
+    framing: &DecodeFraming,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = read_file(file)?;
+    let png = parse_png(&bytes, fix_crc)?;
+    let mut chunks: Vec<&Chunk> = match (type_bytes, chunk_type) {
+        (Some(type_bytes), _) => png
+            .iter()
+            .filter(|chunk| chunk.chunk_type().bytes() == type_bytes)
+            .collect(),
+        (None, Some(chunk_type)) => png.chunks_by_type(chunk_type).collect(),
+        (None, None) => Vec::new(),
+    };
+    if chunks.is_empty() {
+        return Err(PngError::ChunkNotFound().into());
+    }
+    if output.first {
+        chunks.truncate(1);
+    }
+    let left_out = match output.max_messages {
+        Some(max) => chunks.drain(max.min(chunks.len())..).count(),
+        None => 0,
+    };
+
+    if output.length_only {
+        for chunk in chunks {
+            println!("{}", chunk.length());
+        }
+        print_left_out(left_out);
+        return Ok(());
+    }
+
+    let messages = chunks
+        .iter()
+        .map(|chunk| unframe_message(chunk.data(), framing))
+        .collect::<Result<Vec<&[u8]>, _>>()?;
+    if let Some(path) = &output.output {
+        stdio::write_output(path, &messages.concat())?;
+        return Ok(());
+    }
+    for (index, message) in messages.into_iter().enumerate() {
+        if output.all {
+            print_indexed_message(index, message);
+        } else {
+            print_message(message, &output.interpretations, output.hex)?;
+        }
+    }
+    print_left_out(left_out);
+    Ok(())
+}
+
+/// Notes how many messages `decode --max-messages` left out, if any
+fn print_left_out(count: usize) {
+    if count > 0 {
+        println!("... ({} more)", count);
+    }
+}
+
+/// Undoes the padding and hash that `framing` says `encode` added to chunk data
+fn unframe_message<'a>(
+    data: &'a [u8],
+    framing: &DecodeFraming,
+) -> Result<&'a [u8], payload::PayloadError> {
+    let mut message = data;
+    if framing.padded {
+        message = payload::unpad(message)?;
+    }
+    if framing.verify_hash {
+        message = message_with_verified_hash(message);
+    }
+    Ok(message)
+}
+
+/// Prints a decoded message as text, using the first of `interpretations` that yields readable
+/// text if any are given. With `hex`, a message that isn't UTF-8 is printed as hex instead
+fn print_message(
+    message: &[u8],
+    interpretations: &[encoding::Interpretation],
+    hex: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if interpretations.is_empty() {
+        let text = String::from_utf8_lossy(message);
+        if let Cow::Owned(_) = text {
+            if hex {
+                println!("Hidden message (hex): {}", encoding::hex_encode(message));
+                return Ok(());
+            }
+            eprintln!(
+                "Warning: the message is not valid UTF-8, use --hex to see its bytes or --output \
+                 to save them"
+            );
+        }
+        println!("Hidden message: {}", text);
+        return Ok(());
+    }
+
+    match encoding::interpret_first(message, interpretations) {
+        Some((interpretation, message)) => {
+            println!("Hidden message ({}): {}", interpretation, message)
+        }
+        None => return Err("no interpretation of the chunk data produced readable text".into()),
+    }
+    Ok(())
+}
+
+/// Prints a message after its occurrence index, as text if it is UTF-8 or else as its length and
+/// the hex of its first bytes
+fn print_indexed_message(index: usize, message: &[u8]) {
+    const PREVIEW_LENGTH: usize = 16;
+    match std::str::from_utf8(message) {
+        Ok(text) => println!("{}: {}", index, text),
+        Err(_) => {
+            let preview = encoding::hex_encode(&message[..message.len().min(PREVIEW_LENGTH)]);
+            let ellipsis = if message.len() > PREVIEW_LENGTH {
+                "..."
+            } else {
+                ""
+            };
+            println!(
+                "{}: <{} bytes> {}{}",
+                index,
+                message.len(),
+                preview,
+                ellipsis
+            );
+        }
+    }
+}
+
+/// Strips the hash trailer from chunk data, warning if it is missing or doesn't match
+fn message_with_verified_hash(data: &[u8]) -> &[u8] {
+    match payload::verify_hash(data) {
+        Ok(message) => message,
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            payload::split_hash(data).map_or(data, |(message, _)| message)
+        }
+    }
+}
+
+/// Hides a message in the lowest bits of the pixel samples of a PNG file. The file is replaced in
+/// place unless `output_file` is given
+pub fn embed(
+    file: &str,
     message: &str,
     output_file: &Option<String>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let bytes = fs::read(file)?;
-    let mut png = Png::try_from(&bytes[..])?;
+    let mut png = read_png(file)?;
+    stego::embed(&mut png, message.as_bytes())?;
+    match output_file {
+        Some(path) => stdio::write_output(path, &png.as_bytes())?,
+        None => write_in_place(file, &png.as_bytes())?,
+    }
+    Ok(())
+}
 
-    png.append_chunk(Chunk::new(
-        ChunkType::from_str(chunk_type)?,
-        message.as_bytes().to_vec(),
-    ));
+/// Prints the message hidden in the pixel samples of a PNG file by `embed`
+pub fn reveal(file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let png = read_png(file)?;
+    print_message(&stego::extract(&png)?, &[], false)
+}
 
-    match output_file {
-        Some(path) => fs::write(path, png.as_bytes())?,
-        None => fs::write(file, png.as_bytes())?,
+/// Inserts a chunk holding the contents of `data_file` at `index`, wherever that falls. The file is
+/// replaced in place unless `output_file` is given
+pub fn insert(
+    file: &str,
+    chunk_type: &str,
+    data_file: &str,
+    index: usize,
+    output_file: &Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut png = read_png(file)?;
+    let data = read_file(data_file)?;
+    check_message_length(data.len())?;
+    png.insert_chunk_at(index, Chunk::new(ChunkType::from_str(chunk_type)?, data))?;
+    write_png(&png, output_file.as_deref().unwrap_or(file))?;
+    Ok(())
+}
+
+/// Removes the first occurrance of a given chunk type, the `nth` occurrance if given, or every
+/// occurrance if `all` is set. Unless `force` is set, the file is left untouched if the removal
+/// would introduce a structural problem. The file is replaced unless `output_file` is given. A file
+/// of `-` is read from stdin and written to stdout
+pub fn remove(
+    file: &str,
+    chunk_type: &str,
+    force: bool,
+    all: bool,
+    nth: Option<usize>,
+    output_file: &Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut png = read_png(file)?;
+    let before = validate::validate_structure(&png);
+    let removed = if all {
+        png.remove_all_chunks(chunk_type)?.len()
+    } else if let Some(n) = nth {
+        png.remove_nth_chunk(chunk_type, n)?;
+        1
+    } else {
+        png.remove_first_chunk(chunk_type)?;
+        1
+    };
+
+    if !force {
+        let after = validate::validate_structure(&png);
+        let introduced = introduced_issues(&before, &after);
+        if !introduced.is_empty() {
+            let issues: Vec<String> = introduced.iter().map(|i| i.to_string()).collect();
+            return Err(CommandError::RemovalBreaksStructure {
+                chunk_type: chunk_type.to_string(),
+                issues: issues.join("; "),
+            }
+            .into());
+        }
+    }
+
+    let output_file = output_file.as_deref().unwrap_or(file);
+    write_png(&png, output_file)?;
+    if all {
+        report(
+            output_file,
+            format_args!("Removed {} {} chunk(s)", removed, chunk_type),
+        );
     }
+    Ok(())
+}
+
+/// Removes every ancillary chunk whose type isn't in `keep`, printing how many chunks and bytes
+/// were removed. The file isn't rewritten in place if nothing was removed
+pub fn strip(
+    file: &str,
+    keep: &[String],
+    output_file: &Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut png = read_png(file)?;
+    let removed = png.retain(|chunk| {
+        chunk.chunk_type().is_critical() || keep.contains(&chunk.chunk_type().to_string())
+    });
 
+    let target = output_file.as_deref().unwrap_or(file);
+    if !removed.is_empty() || output_file.is_some() || stdio::is_stdio(file) {
+        write_png(&png, target)?;
+    }
+    let removed_bytes: usize = removed.iter().map(|chunk| chunk.as_bytes().len()).sum();
+    report(
+        target,
+        format_args!(
+            "Removed {} chunk(s), {} bytes",
+            removed.len(),
+            removed_bytes
+        ),
+    );
     Ok(())
 }
 
-/// Decode prints the data within the first occurrance of a given chunk type
-pub fn decode(file: &str, chunk_type: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let bytes = fs::read(file)?;
-    let mut png = Png::try_from(&bytes[..])?;
-    let chunk = png.remove_first_chunk(chunk_type)?;
-    println!("Hidden message: {}", chunk.data_as_string()?);
+/// Issues in `after` of a kind that occurs more often than in `before`. Kinds are compared
+/// rather than whole issues since chunk indices shift when a chunk is removed
+fn introduced_issues<'a>(
+    before: &[ValidationIssue],
+    after: &'a [ValidationIssue],
+) -> Vec<&'a ValidationIssue> {
+    let count =
+        |issues: &[ValidationIssue], code| issues.iter().filter(|i| i.code() == code).count();
+    after
+        .iter()
+        .filter(|issue| count(after, issue.code()) > count(before, issue.code()))
+        .collect()
+}
+
+/// Splits IDAT chunks with more than `max_idat` bytes of data, or merges consecutive IDAT chunks
+pub fn rechunk(
+    file: &str,
+    max_idat: Option<u32>,
+    merge_idat: bool,
+    output_file: &Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut png = read_png(file)?;
+    if merge_idat {
+        png.merge_idat()?;
+    }
+    if let Some(max_idat) = max_idat {
+        png.rechunk_idat(max_idat as usize);
+    }
+
+    write_png(&png, output_file.as_deref().unwrap_or(file))?;
+
     Ok(())
 }
 
-/// Removes the first occurrance of a given chunk type
-pub fn remove(file: &str, chunk_type: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let bytes = fs::read(file)?;
-    let mut png = Png::try_from(&bytes[..])?;
-    png.remove_first_chunk(chunk_type)?;
-    fs::write(file, png.as_bytes())?;
+/// Rewrites a PNG file in canonical form, running every step not turned off in `steps`, and prints
+/// what each step changed. Only ancillary chunks are dropped and IDAT chunks keep their order, so
+/// the image is unchanged
+pub fn canonicalize(
+    file: &str,
+    steps: &CanonicalizeSteps,
+    output_file: &Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let output_file = output_file.as_deref().unwrap_or(file);
+    let bytes = read_file(file)?;
+    let mut png = if steps.no_fix_crc {
+        Png::try_from(&bytes[..])?
+    } else {
+        Png::try_from_lenient(&bytes)?
+    };
+    if png.signature() != &Png::STANDARD_HEADER {
+        return Err(PngError::InvalidSignature().into());
+    }
+
+    if !steps.no_fix_crc {
+        report(
+            output_file,
+            format_args!("Fixed {} CRC(s)", png.recalculate_crcs()),
+        );
+    }
+    if !steps.no_reorder {
+        if png.normalize_order() {
+            report(output_file, format_args!("Reordered chunks"));
+        } else {
+            report(output_file, format_args!("Chunk order already canonical"));
+        }
+    }
+    if !steps.no_drop_empty {
+        let dropped = png
+            .retain(|chunk| chunk.chunk_type().is_critical() || chunk.length() > 0)
+            .len();
+        report(
+            output_file,
+            format_args!("Dropped {} empty chunk(s)", dropped),
+        );
+    }
+    if !steps.no_dedup {
+        report(
+            output_file,
+            format_args!(
+                "Dropped {} duplicate chunk(s)",
+                png.remove_duplicate_ancillary()
+            ),
+        );
+    }
+    if !steps.no_merge_idat {
+        let idat_count = |png: &Png| png.chunks_by_type("IDAT").count();
+        let before = idat_count(&png);
+        png.merge_idat()?;
+        let after = idat_count(&png);
+        if after < before {
+            report(
+                output_file,
+                format_args!("Merged {} IDAT chunk(s) into {}", before, after),
+            );
+        } else {
+            report(output_file, format_args!("IDAT chunks already merged"));
+        }
+    }
+
+    write_png(&png, output_file)?;
     Ok(())
 }
 
-/// Prints the contents of a PNG file
-pub fn print(file: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let bytes = fs::read(file)?;
+/// Prints the contents of a PNG file, one chunk per line. With `all_streams`, each PNG stream
+/// appended to the file is listed under its own heading
+pub fn print(
+    file: &str,
+    canonical: bool,
+    fix_crc: bool,
+    describe: bool,
+    all_streams: bool,
+    filter: &ChunkFilter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = read_file(file)?;
+    if all_streams {
+        for (i, png) in Png::try_from_streams(&bytes, fix_crc)?.iter().enumerate() {
+            if fix_crc {
+                warn_invalid_crcs(png);
+            }
+            let chunks = png.chunks().iter().filter(|chunk| filter.matches(chunk));
+            println!("stream {}:", i);
+            print!("{}", format_chunks(chunks, describe));
+        }
+        return Ok(());
+    }
+
+    let png = parse_png(&bytes, fix_crc)?;
+    let chunks = png.chunks().iter().filter(|chunk| filter.matches(chunk));
+    if canonical {
+        print!("{}", canonical::to_canonical(chunks));
+    } else {
+        print!("{}", format_chunks(chunks, describe));
+    }
+    Ok(())
+}
+
+/// Formats chunks for `print`, one per line, optionally followed by a description of the type
+fn format_chunks<'a>(chunks: impl IntoIterator<Item = &'a Chunk>, describe: bool) -> String {
+    chunks
+        .into_iter()
+        .map(|chunk| {
+            if describe {
+                let description = chunk.chunk_type().description();
+                format!(
+                    "{}  ({})\n",
+                    chunk,
+                    description.unwrap_or("private/unknown")
+                )
+            } else {
+                format!("{}\n", chunk)
+            }
+        })
+        .collect()
+}
+
+/// Runs every validation check on a PNG file and reports an overall status
+pub fn audit(file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = read_file(file)?;
+    let (_, issues) = validate_all(&bytes)?;
+
+    for issue in &issues {
+        println!("{}: {}", issue.severity(), issue);
+    }
+
+    let count = |severity| issues.iter().filter(|i| i.severity() == severity).count();
+    let (errors, warnings) = (count(Severity::Error), count(Severity::Warning));
+    if errors > 0 {
+        println!("status: fail");
+        Err(CommandError::AuditErrors(errors).into())
+    } else if warnings > 0 {
+        println!("status: warn");
+        Err(CommandError::AuditWarnings(warnings).into())
+    } else {
+        println!("status: pass");
+        Ok(())
+    }
+}
+
+/// Prints the number of chunks and bytes in a PNG file and the image fields of its IHDR chunk,
+/// and optionally a palette summary and the tool that wrote it
+pub fn info(file: &str, palette: bool, producer: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = read_file(file)?;
     let png = Png::try_from(&bytes[..])?;
-    println!("{}", png);
+    println!("{} chunks, {} bytes", png.chunks().len(), bytes.len());
+
+    let ihdr = png.chunk_by_type("IHDR").ok_or(PngError::ChunkNotFound())?;
+    let ihdr = IhdrData::try_from(ihdr)?;
+    println!("dimensions: {}x{}", ihdr.width, ihdr.height);
+    println!("bit depth: {}", ihdr.bit_depth);
+    println!(
+        "colour type: {} ({})",
+        ihdr.color_type,
+        ihdr.color_type_name().unwrap_or("unknown")
+    );
+    let interlace = match ihdr.interlace_method {
+        0 => "none",
+        1 => "Adam7",
+        _ => "unknown",
+    };
+    println!("interlace: {}", interlace);
+
+    if palette {
+        match palette::palette_summary(&png) {
+            Some(summary) => println!("palette: {}", summary),
+            None => println!("palette: none, not a palette image"),
+        }
+    }
+    if producer {
+        let producer = text::producer(&png);
+        println!("producer: {}", producer.as_deref().unwrap_or("unknown"));
+    }
+    Ok(())
+}
+
+/// Prints one line per chunk with its index, type, length, CRC, offset and type flags
+pub fn list(file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let png = read_png(file)?;
+    print!("{}", list_lines(&png));
     Ok(())
 }
+
+/// Formats the `list` output, with the numeric columns right-aligned to the widest value. Offsets
+/// are where each chunk's length field starts in the file
+fn list_lines(png: &Png) -> String {
+    let chunks = png.chunks();
+    let offsets: Vec<usize> = chunks
+        .iter()
+        .scan(png.signature().len(), |offset, chunk| {
+            let start = *offset;
+            *offset += 12 + chunk.data().len();
+            Some(start)
+        })
+        .collect();
+    let index_width = chunks.len().saturating_sub(1).to_string().len();
+    let length_width = chunks
+        .iter()
+        .map(|chunk| chunk.length().to_string().len())
+        .max()
+        .unwrap_or(1);
+    let offset_width = offsets.last().map_or(1, |offset| offset.to_string().len());
+
+    chunks
+        .iter()
+        .zip(offsets)
+        .enumerate()
+        .map(|(index, (chunk, offset))| {
+            let chunk_type = chunk.chunk_type();
+            let flags = [
+                if chunk_type.is_critical() {
+                    "critical"
+                } else {
+                    "ancillary"
+                },
+                if chunk_type.is_public() {
+                    "public"
+                } else {
+                    "private"
+                },
+                if chunk_type.is_safe_to_copy() {
+                    "safe-to-copy"
+                } else {
+                    "unsafe-to-copy"
+                },
+            ];
+            format!(
+                "{:>index_width$}  {}  {:>length_width$}  {:08x}  {:>offset_width$}  {}\n",
+                index,
+                chunk_type,
+                chunk.length(),
+                chunk.crc(),
+                offset,
+                flags.join(",")
+            )
+        })
+        .collect()
+}
+
+/// Prints a one-line summary of a PNG file
+pub fn stat(file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = read_file(file)?;
+    match stat_line(&bytes) {
+        Ok(line) => println!("{}", line),
+        Err(reason) => println!("invalid: {}", reason),
+    }
+    Ok(())
+}
+
+/// Formats the `stat` summary, e.g. `50x50 RGBA-8, 7 chunks, 3 ancillary, 4.8 KB, valid`. Fails
+/// with the reason the file is invalid, where only errors count and not warnings
+fn stat_line(bytes: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+    let png = Png::try_from(bytes)?;
+    let (_, issues) = validate_all(bytes)?;
+    if let Some(error) = issues.iter().find(|i| i.severity() == Severity::Error) {
+        return Err(error.to_string().into());
+    }
+
+    let ihdr = png.chunk_by_type("IHDR").ok_or(PngError::ChunkNotFound())?;
+    let ihdr = IhdrData::try_from(ihdr)?;
+    let ancillary = png
+        .chunks()
+        .iter()
+        .filter(|chunk| !chunk.chunk_type().is_critical())
+        .count();
+
+    Ok(format!(
+        "{}x{} {}-{}, {} chunks, {} ancillary, {}, valid",
+        ihdr.width,
+        ihdr.height,
+        ihdr.color_type_name().unwrap_or("unknown"),
+        ihdr.bit_depth,
+        png.chunks().len(),
+        ancillary,
+        format_size(bytes.len())
+    ))
+}
+
+/// Formats a byte count with decimal units, e.g. `1.2 MB`
+fn format_size(bytes: usize) -> String {
+    const UNITS: [&str; 3] = ["KB", "MB", "GB"];
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Prints each validation issue as `<code> <offset> <message>`, where the offset is the byte offset
+/// of the chunk concerned or `-`
+pub fn lint(file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = read_file(file)?;
+    let (png, issues) = validate_all(&bytes)?;
+
+    for issue in &issues {
+        let offset = png
+            .as_ref()
+            .zip(issue.chunk_index())
+            .and_then(|(png, index)| png.byte_range_of_chunk(index));
+        match offset {
+            Some(range) => println!("{} {} {}", issue.code(), range.start, issue),
+            None => println!("{} - {}", issue.code(), issue),
+        }
+    }
+
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(CommandError::LintFindings(issues.len()).into())
+    }
+}
+
+/// Recalculates every incorrect chunk CRC in a PNG file. The file isn't written if every CRC is
+/// already correct, unless it goes to stdout
+pub fn fix(file: &str, output_file: &Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = read_file(file)?;
+    let mut png = Png::try_from_lenient(&bytes)?;
+    let fixed = png.recalculate_crcs();
+    let output_file = output_file.as_deref().unwrap_or(file);
+    if fixed > 0 || stdio::is_stdio(output_file) {
+        write_png(&png, output_file)?;
+    }
+    report(output_file, format_args!("Fixed {} CRC(s)", fixed));
+    Ok(())
+}
+
+/// Checks a PNG file's signature, chunk CRCs, critical chunk order and data after IEND, printing
+/// every problem found. With `strict`, the placement of ancillary chunks is checked too
+pub fn verify(file: &str, strict: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = read_file(file)?;
+    let (png, trailing) = Png::try_from_lenient_until_iend(&bytes)?;
+    let mut issues = png.validate();
+    if strict {
+        issues.extend(validate::validate_ancillary_placement(&png));
+    }
+    if !trailing.is_empty() {
+        issues.push(ValidationIssue::TrailingData {
+            length: trailing.len(),
+        });
+    }
+
+    for issue in &issues {
+        println!("{}: {}", issue.severity(), issue);
+    }
+    if issues.is_empty() {
+        println!("ok");
+        Ok(())
+    } else {
+        Err(CommandError::VerifyFindings(issues.len()).into())
+    }
+}
+
+/// Replaces every chunk of a given type with a tEXt chunk holding its data as text
+pub fn migrate(
+    file: &str,
+    chunk_type: &str,
+    keyword: &str,
+    output_file: &Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut png = read_png(file)?;
+    migrate_to_text(&mut png, chunk_type, keyword)?;
+    write_png(&png, output_file.as_deref().unwrap_or(file))?;
+    Ok(())
+}
+
+/// Replaces each chunk of a given type with a tEXt chunk in the same position, returning how
+/// many were replaced
+fn migrate_to_text(
+    png: &mut Png,
+    chunk_type: &str,
+    keyword: &str,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    if text::TEXT_CHUNK_TYPES.contains(&chunk_type) {
+        return Err(CommandError::AlreadyText(chunk_type.to_string()).into());
+    }
+
+    let indices: Vec<usize> = png
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| chunk.chunk_type().to_string() == chunk_type)
+        .map(|(index, _)| index)
+        .collect();
+    if indices.is_empty() {
+        return Err(PngError::ChunkNotFound().into());
+    }
+
+    for &index in &indices {
+        let replacement = text::text_chunk(keyword, &png.chunks()[index].data_as_string()?)?;
+        png.replace_chunk(index, replacement);
+    }
+    Ok(indices.len())
+}
+
+/// Sets a tEXt entry for each keyword and text pair. Nothing is written if any keyword is invalid
+pub fn meta_set(
+    file: &str,
+    entries: &[(String, String)],
+    output_file: &Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut png = read_png(file)?;
+    for (keyword, text) in entries {
+        text::set_text(&mut png, keyword, text)?;
+    }
+    write_png(&png, output_file.as_deref().unwrap_or(file))?;
+    Ok(())
+}
+
+/// Prints the text of the first text chunk with the keyword, failing if there is none
+pub fn meta_get(file: &str, keyword: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let png = read_png(file)?;
+    let entry = text::text_entries(&png)
+        .find(|entry| entry.keyword == keyword)
+        .ok_or_else(|| format!("no text entry with keyword {:?}", keyword))?;
+    println!("{}", entry.text);
+    Ok(())
+}
+
+/// Removes every text chunk with the keyword. The file isn't rewritten in place if there are none,
+/// but an output file is always written
+pub fn meta_unset(
+    file: &str,
+    keyword: &str,
+    output_file: &Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut png = read_png(file)?;
+    let removed = text::remove_text(&mut png, keyword);
+    if removed > 0 || output_file.is_some() || stdio::is_stdio(file) {
+        write_png(&png, output_file.as_deref().unwrap_or(file))?;
+    }
+    Ok(())
+}
+
+/// Prints `keyword: text` for each text chunk in file order
+pub fn text(file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let png = read_png(file)?;
+    for line in text_lines(&png) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Formats each text chunk as `keyword: text`, skipping chunks that can't be decoded with a
+/// warning
+fn text_lines(png: &Png) -> Vec<String> {
+    let is_text = |chunk: &&Chunk| {
+        let chunk_type = chunk.chunk_type().to_string();
+        text::TEXT_CHUNK_TYPES.contains(&chunk_type.as_str())
+    };
+
+    png.chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| is_text(chunk))
+        .filter_map(|(i, chunk)| match text::parse_text_chunk(chunk) {
+            Ok(entry) => Some(format!("{}: {}", entry.keyword, entry.text)),
+            Err(e) => {
+                eprintln!(
+                    "Warning: skipping chunk {} ({}): {}",
+                    i,
+                    chunk.chunk_type(),
+                    e
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+/// Writes the data of the first chunk of a given type, or of the `nth`, to a file, optionally
+/// decompressed. With `raw`, the whole chunk is written including its length, type and CRC. With
+/// `all`, every chunk of the type is written one after another
+pub fn extract(
+    file: &str,
+    chunk_type: &str,
+    output_file: &str,
+    decompress: bool,
+    raw: bool,
+    nth: Option<usize>,
+    all: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let png = read_png(file)?;
+    let chunk_bytes = |chunk: &Chunk| {
+        if raw {
+            chunk.as_bytes()
+        } else {
+            chunk.data().to_vec()
+        }
+    };
+
+    if all {
+        let chunks: Vec<&Chunk> = png.chunks_by_type(chunk_type).collect();
+        if chunks.is_empty() {
+            return Err(PngError::ChunkNotFound().into());
+        }
+        let data: Vec<u8> = chunks.into_iter().flat_map(chunk_bytes).collect();
+        stdio::write_output(output_file, &data)?;
+        return Ok(());
+    }
+
+    let chunk = match nth {
+        Some(n) => png.nth_chunk_by_type(chunk_type, n)?,
+        None => png
+            .chunk_by_type(chunk_type)
+            .ok_or(PngError::ChunkNotFound())?,
+    };
+
+    if decompress {
+        stdio::write_output(output_file, &zlib::decompress_chunk(chunk)?)?;
+    } else {
+        stdio::write_output(output_file, &chunk_bytes(chunk))?;
+    }
+    Ok(())
+}
+
+/// Writes the ICC profile from the iCCP chunk of a PNG file and prints the profile name
+pub fn icc(file: &str, output_file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let png = read_png(file)?;
+    let (name, profile) = icc_profile(&png)?;
+    stdio::write_output(output_file, &profile)?;
+    report(output_file, format_args!("Profile: {}", name));
+    Ok(())
+}
+
+/// The name and inflated data of the ICC profile in the iCCP chunk
+fn icc_profile(png: &Png) -> Result<(String, Vec<u8>), Box<dyn std::error::Error>> {
+    let chunk = png
+        .chunk_by_type("iCCP")
+        .ok_or("no iCCP chunk, the image has no embedded ICC profile")?;
+    let (name, _) = chunk
+        .data_split_first(0)
+        .ok_or("iCCP chunk has no NUL after the profile name")?;
+    let profile = zlib::decompress_chunk(chunk)?;
+    Ok((text::latin1_to_string(name), profile))
+}
+
+/// Prints `<fingerprint>  <path>` for every PNG file under a directory, in path order
+pub fn manifest(dir: &str) -> Result<(), Box<dyn std::error::Error>> {
+    for path in find_png_files(Path::new(dir))? {
+        let bytes = fs::read(&path)?;
+        let png = Png::try_from(&bytes[..]).map_err(|e| format!("{}: {}", path.display(), e))?;
+        println!(
+            "{}  {}",
+            encoding::hex_encode(&png.critical_fingerprint()?),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Recomputes the fingerprint of each file in a manifest, printing `OK` or `CHANGED` per file
+pub fn check_manifest(manifest: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let text = String::from_utf8(read_file(manifest)?)?;
+    let mut mismatches = 0;
+    for line in text.lines().filter(|line| !line.is_empty()) {
+        let (fingerprint, path) = line
+            .split_once("  ")
+            .ok_or_else(|| format!("invalid manifest line: {}", line))?;
+
+        let status = match fingerprint_file(Path::new(path)) {
+            Ok(actual) if actual == fingerprint => "OK".to_string(),
+            Ok(_) => "CHANGED".to_string(),
+            Err(e) => format!("FAILED ({})", e),
+        };
+        if status != "OK" {
+            mismatches += 1;
+        }
+        println!("{}: {}", path, status);
+    }
+
+    if mismatches > 0 {
+        return Err(CommandError::ManifestMismatch(mismatches).into());
+    }
+    Ok(())
+}
+
+/// Hex critical fingerprint of a PNG file
+fn fingerprint_file(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let bytes = fs::read(path)?;
+    let png = Png::try_from(&bytes[..])?;
+    Ok(encoding::hex_encode(&png.critical_fingerprint()?))
+}
+
+/// Lists the files with a `.png` extension under a directory and its subdirectories, sorted by
+/// path. Symbolic links are not followed
+fn find_png_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.path());
+
+    for entry in entries {
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            files.extend(find_png_files(&path)?);
+        } else if file_type.is_file()
+            && path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+        {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Checks that the data of the first chunk of a given type is identical to a file's contents
+pub fn verify_data(
+    file: &str,
+    chunk_type: &str,
+    expected_file: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let png = read_png(file)?;
+    let chunk = png
+        .chunk_by_type(chunk_type)
+        .ok_or(PngError::ChunkNotFound())?;
+    let expected = read_file(expected_file)?;
+
+    match first_difference(chunk.data(), &expected) {
+        Some(offset) => Err(CommandError::DataMismatch {
+            file: expected_file.to_string(),
+            offset,
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
+
+/// Offset of the first byte that differs, or the length of the shorter slice if one is a prefix
+/// of the other
+fn first_difference(a: &[u8], b: &[u8]) -> Option<usize> {
+    a.iter()
+        .zip(b)
+        .position(|(x, y)| x != y)
+        .or_else(|| (a.len() != b.len()).then(|| a.len().min(b.len())))
+}
+
+/// Builds a PNG file from a canonical text dump
+pub fn import(file: &str, output_file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let text = String::from_utf8(read_file(file)?)?;
+    let png = canonical::from_canonical(&text)?;
+    write_png(&png, output_file)?;
+    Ok(())
+}
+
+/// Writes a PNG to a file, or to stdout for `-`
+fn write_png(png: &Png, file: &str) -> Result<(), PngError> {
+    if stdio::is_stdio(file) {
+        return Ok(stdio::write_output(file, &png.as_bytes())?);
+    }
+    png.write_file(file)
+}
+
+/// Prints a summary line to stdout, or to stderr when the output file is stdout so the summary
+/// doesn't end up in the written bytes
+fn report(output_file: &str, line: fmt::Arguments) {
+    if stdio::is_stdio(output_file) {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// Chunk lengths are stored in 4 bytes, so longer messages would silently wrap
+fn check_message_length(length: usize) -> Result<(), CommandError> {
+    match u32::try_from(length) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(CommandError::MessageTooLong(length)),
+    }
+}
+
+/// Reads bytes up to, but not including, the first NUL byte or the end of the input
+fn read_until_nul(mut reader: impl BufRead) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    reader.read_until(0, &mut bytes)?;
+    if bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+    Ok(bytes)
+}
+
+/// Reads a whole file, or stdin for `-`, reporting a friendly error if the path is a directory
+fn read_file(file: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    check_not_directory(file)?;
+    Ok(stdio::read_input(file)?)
+}
+
+/// Reads and parses a PNG file, or stdin for `-`, reporting a friendly error if the path is a
+/// directory
+fn read_png(file: &str) -> Result<Png, Box<dyn std::error::Error>> {
+    if stdio::is_stdio(file) {
+        return Ok(Png::try_from(&stdio::read_input(file)?[..])?);
+    }
+    check_not_directory(file)?;
+    Ok(Png::from_file(file)?)
+}
+
+/// Fails with a friendly error if the path is a directory rather than a file
+fn check_not_directory(file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !stdio::is_stdio(file) && fs::metadata(file)?.is_dir() {
+        return Err(CommandError::IsDirectory(file.to_string()).into());
+    }
+    Ok(())
+}
+
+/// Runs every validation check. The PNG is only parsed, leniently, if the signature is valid
+fn validate_all(bytes: &[u8]) -> Result<(Option<Png>, Vec<ValidationIssue>), PngError> {
+    let issues = validate::validate_signature(bytes);
+    if !issues.is_empty() {
+        return Ok((None, issues));
+    }
+
+    let png = Png::try_from_lenient(bytes)?;
+    let mut issues = validate::validate_structure(&png);
+    issues.extend(validate::validate_crcs(&png));
+    issues.extend(validate::validate_chunk_types(&png));
+    issues.extend(validate::find_private_chunks(&png));
+    Ok((Some(png), issues))
+}
+
+/// Parses a PNG. With `fix_crc`, chunks with an incorrect CRC and a non-standard signature are
+/// accepted with a warning
+fn parse_png(bytes: &[u8], fix_crc: bool) -> Result<Png, PngError> {
+    if !fix_crc {
+        return Png::try_from(bytes);
+    }
+
+    let png = Png::try_from_lenient(bytes)?;
+    if png.signature() != &Png::STANDARD_HEADER {
+        eprintln!("Warning: file does not start with the PNG signature");
+    }
+    warn_invalid_crcs(&png);
+    Ok(png)
+}
+
+/// Prints a warning for each chunk whose CRC doesn't match its contents
+fn warn_invalid_crcs(png: &Png) {
+    for (i, chunk) in png.chunks().iter().enumerate() {
+        if !chunk.is_crc_valid() {
+            eprintln!(
+                "Warning: chunk {} ({}) has CRC {:08x}, expected {:08x}",
+                i,
+                chunk.chunk_type(),
+                chunk.crc(),
+                chunk.calculated_crc()
+            );
+        }
+    }
+}
+
+/// Warning shown when a chunk type is a case variant of a registered type
+fn shadowing_warning(chunk_type: &ChunkType) -> Option<String> {
+    chunk_type.shadowed_registered_type().map(|registered| {
+        format!(
+            "Warning: chunk type {} differs from the registered {} chunk only by case, \
+            consider a distinct name",
+            chunk_type, registered
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::NoTimePolicy;
+
+    #[test]
+    fn test_read_file_rejects_directory() {
+        let dir = std::env::temp_dir();
+        let filter = ChunkFilter::default();
+        let err = print(dir.to_str().unwrap(), false, false, false, false, &filter).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CommandError>(),
+            Some(CommandError::IsDirectory(_))
+        ));
+    }
+
+    #[test]
+    fn test_print_exclude() {
+        let mut png = Png::default();
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            b"hidden".to_vec(),
+        ));
+        let filter = ChunkFilter {
+            exclude: vec!["IDAT".to_string(), "IEND".to_string()],
+            ..Default::default()
+        };
+
+        let output = format_chunks(png.chunks().iter().filter(|c| filter.matches(c)), false);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("IHDR"));
+        assert!(lines[1].contains("ruSt"));
+        assert!(!output.contains("IDAT"));
+    }
+
+    #[test]
+    fn test_print_size_limits() {
+        let png = Png::default();
+        let types = |filter: ChunkFilter| -> Vec<String> {
+            png.chunks()
+                .iter()
+                .filter(|c| filter.matches(c))
+                .map(|c| c.chunk_type().to_string())
+                .collect()
+        };
+
+        // IHDR holds 13 bytes, IDAT 10 and IEND none
+        let filter = ChunkFilter {
+            min_size: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(types(filter), ["IHDR", "IDAT"]);
+
+        let filter = ChunkFilter {
+            min_size: Some(1),
+            max_size: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(types(filter), ["IDAT"]);
+    }
+
+    #[test]
+    fn test_print_describe() {
+        let mut png = Png::default();
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            b"hidden".to_vec(),
+        ));
+
+        let output = format_chunks(png.chunks(), true);
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].ends_with("  (image header)"));
+        assert!(lines[3].ends_with("  (private/unknown)"));
+    }
+
+    #[test]
+    fn test_list_lines() {
+        let output = list_lines(&Png::default());
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("0  IHDR  13  "));
+        assert!(lines[0].ends_with("   8  critical,public,unsafe-to-copy"));
+        assert!(lines[1].starts_with("1  IDAT  10  "));
+        assert!(lines[1].contains("  33  critical"));
+        assert!(lines[2].starts_with("2  IEND   0  ae426082  55  "));
+    }
+
+    #[test]
+    fn test_text_lines() {
+        let mut compressed = b"Comment\0\0".to_vec();
+        compressed.extend([
+            120, 218, 243, 77, 76, 73, 85, 40, 207, 44, 201, 80, 40, 200, 75, 207, 77, 5, 0, 42,
+            251, 5, 139,
+        ]);
+        let png = Png::from_chunks(vec![
+            Chunk::new(
+                ChunkType::from_str("tEXt").unwrap(),
+                b"Title\0Dice".to_vec(),
+            ),
+            Chunk::new(ChunkType::from_str("zTXt").unwrap(), compressed),
+            Chunk::new(ChunkType::from_str("zTXt").unwrap(), b"Broken".to_vec()),
+        ]);
+
+        assert_eq!(
+            text_lines(&png),
+            ["Title: Dice", "Comment: Made with pngme"]
+        );
+    }
+
+    #[test]
+    fn test_remove_refuses_to_break_structure() {
+        let palette_ihdr = vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 3, 0, 0, 0];
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), palette_ihdr),
+            Chunk::new(ChunkType::from_str("PLTE").unwrap(), vec![0; 3]),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ]);
+        let path = std::env::temp_dir().join(format!("pngme-remove-{}.png", std::process::id()));
+        png.write_file(&path).unwrap();
+        let path = path.to_str().unwrap();
+
+        let err = remove(path, "PLTE", false, false, None, &None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CommandError>(),
+            Some(CommandError::RemovalBreaksStructure { .. })
+        ));
+        assert_eq!(fs::read(path).unwrap(), png.as_bytes());
+
+        remove(path, "PLTE", true, false, None, &None).unwrap();
+        let png = Png::try_from(&fs::read(path).unwrap()[..]).unwrap();
+        assert!(png.chunk_by_type("PLTE").is_none());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_to_text() {
+        let chunk = |chunk_type, data: &[u8]| {
+            Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+        };
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("ruSt", b"hidden"),
+            chunk("IDAT", &[]),
+            chunk("IEND", &[]),
+        ]);
+
+        assert_eq!(migrate_to_text(&mut png, "ruSt", "Comment").unwrap(), 1);
+        assert!(png.chunk_by_type("ruSt").is_none());
+
+        let migrated = &png.chunks()[1];
+        assert_eq!(migrated.chunk_type().to_string(), "tEXt");
+        let entry = text::parse_text_chunk(migrated).unwrap();
+        assert_eq!(entry.keyword, "Comment");
+        assert_eq!(entry.text, "hidden");
+
+        // Text chunks can't be migrated, as tEXt text can't hold their keyword and NUL separator
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            text::text_chunk("Title", "a").unwrap(),
+            text::text_chunk("Author", "b").unwrap(),
+            chunk("IEND", &[]),
+        ]);
+        assert!(migrate_to_text(&mut png, "tEXt", "Comment").is_err());
+        assert_eq!(
+            text::parse_text_chunk(&png.chunks()[2]).unwrap().keyword,
+            "Author"
+        );
+    }
+
+    #[test]
+    fn test_icc_profile() {
+        let mut png = Png::default();
+        assert!(icc_profile(&png).is_err());
+
+        let mut data = b"Display P3\0\0".to_vec();
+        data.extend([
+            120, 218, 99, 16, 84, 50, 118, 9, 77, 43, 239, 152, 185, 106, 247, 153, 187, 239, 254,
+            51, 16, 224, 3, 0, 6, 70, 23, 233,
+        ]);
+        let iccp = Chunk::new(ChunkType::from_str("iCCP").unwrap(), data);
+        png.insert_after_type("IHDR", iccp).unwrap();
+
+        let (name, profile) = icc_profile(&png).unwrap();
+        assert_eq!(name, "Display P3");
+        let expected: Vec<u8> = (0..=255).step_by(17).cycle().take(48).collect();
+        assert_eq!(profile, expected);
+
+        let mut data = b"Display P3\0\x01".to_vec();
+        data.extend([120, 218, 3, 0, 0, 0, 0, 1]);
+        let iccp = Chunk::new(ChunkType::from_str("iCCP").unwrap(), data);
+        png.replace_first_chunk("iCCP", iccp).unwrap();
+        let err = icc_profile(&png).unwrap_err();
+        assert!(err.to_string().contains("unknown compression method"));
+    }
+
+    #[test]
+    fn test_write_in_place() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("pngme-in-place-{}.png", std::process::id()));
+        fs::write(&path, b"old").unwrap();
+
+        write_in_place(path.to_str().unwrap(), b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        let name = path.file_name().unwrap().to_string_lossy();
+        assert!(!dir.join(format!(".{}.pngme-tmp", name)).exists());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_in_place_follows_symlink_and_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir();
+        let target = dir.join(format!("pngme-in-place-target-{}.png", std::process::id()));
+        let link = dir.join(format!("pngme-in-place-link-{}.png", std::process::id()));
+        fs::write(&target, b"old").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o640)).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_in_place(link.to_str().unwrap(), b"new").unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        fs::remove_file(link).unwrap();
+        fs::remove_file(target).unwrap();
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(999), "999 B");
+        assert_eq!(format_size(4803), "4.8 KB");
+        assert_eq!(format_size(1_234_567), "1.2 MB");
+        assert_eq!(format_size(5_000_000_000_000), "5000.0 GB");
+    }
+
+    #[test]
+    fn test_time_filter() {
+        let with_time = |data: [u8; 7]| {
+            let mut png = Png::default();
+            let time = Chunk::new(ChunkType::from_str("tIME").unwrap(), data.to_vec());
+            png.insert_after_type("IHDR", time).unwrap();
+            png
+        };
+        let older = with_time([7, 231, 6, 1, 12, 0, 0]);
+        let newer = with_time([7, 232, 3, 1, 12, 0, 0]);
+
+        let filter = TimeFilter {
+            since: Some("2024-01-01".parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(!filter.allows(&older).unwrap());
+        assert!(filter.allows(&newer).unwrap());
+        assert!(filter.allows(&Png::default()).unwrap());
+
+        let filter = TimeFilter {
+            no_time_policy: NoTimePolicy::Skip,
+            ..filter
+        };
+        assert!(!filter.allows(&Png::default()).unwrap());
+        assert!(TimeFilter::default().allows(&older).unwrap());
+    }
+
+    #[test]
+    fn test_first_difference() {
+        assert_eq!(first_difference(b"hey", b"hey"), None);
+        assert_eq!(first_difference(b"hey", b"hex"), Some(2));
+        assert_eq!(first_difference(b"hey", b"he"), Some(2));
+        assert_eq!(first_difference(b"", b"a"), Some(0));
+    }
+
+    #[test]
+    fn test_check_message_length() {
+        assert!(check_message_length(0).is_ok());
+        assert!(check_message_length(u32::MAX as usize).is_ok());
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn test_check_message_length_too_long() {
+        let length = u32::MAX as usize + 1;
+        assert!(matches!(
+            check_message_length(length),
+            Err(CommandError::MessageTooLong(l)) if l == length
+        ));
+    }
+
+    #[test]
+    fn test_strip_trailing_newline() {
+        for (message, expected) in [
+            (&b"secret\n"[..], &b"secret"[..]),
+            (b"secret\r\n", b"secret"),
+            (b"secret\n\n", b"secret\n"),
+            (b"secret", b"secret"),
+            (b"", b""),
+        ] {
+            let mut message = message.to_vec();
+            strip_trailing_newline(&mut message);
+            assert_eq!(message, expected);
+        }
+    }
+
+    #[test]
+    fn test_read_until_nul() {
+        let input: &[u8] = b"first line\nsecond line\0ignored";
+        assert_eq!(read_until_nul(input).unwrap(), b"first line\nsecond line");
+
+        let input: &[u8] = b"no terminator";
+        assert_eq!(read_until_nul(input).unwrap(), b"no terminator");
+    }
+
+    #[test]
+    fn test_shadowing_warning() {
+        let chunk_type = ChunkType::from_str("tExt").unwrap();
+        let warning = shadowing_warning(&chunk_type).unwrap();
+        assert!(warning.contains("tExt"));
+        assert!(warning.contains("tEXt"));
+
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        assert!(shadowing_warning(&chunk_type).is_none());
+    }
+}
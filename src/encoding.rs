@@ -0,0 +1,186 @@
+use clap::ValueEnum;
+use std::{fmt, str};
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Ways the data of a chunk can be interpreted as a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Interpretation {
+    /// The data is the message itself
+    Utf8,
+    /// The data is the standard base64 encoding of the message
+    Base64,
+    /// The data is the hexadecimal encoding of the message
+    Hex,
+}
+
+impl fmt::Display for Interpretation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interpretation::Utf8 => write!(f, "utf8"),
+            Interpretation::Base64 => write!(f, "base64"),
+            Interpretation::Hex => write!(f, "hex"),
+        }
+    }
+}
+
+impl Interpretation {
+    /// Interprets the data, succeeding only if the result is printable UTF-8 text
+    pub fn interpret(self, data: &[u8]) -> Option<String> {
+        let bytes = match self {
+            Interpretation::Utf8 => data.to_vec(),
+            Interpretation::Base64 => base64_decode(str::from_utf8(data).ok()?)?,
+            Interpretation::Hex => hex_decode(str::from_utf8(data).ok()?)?,
+        };
+
+        let text = String::from_utf8(bytes).ok()?;
+        if text.chars().all(|c| !c.is_control() || c.is_whitespace()) {
+            Some(text)
+        } else {
+            None
+        }
+    }
+}
+
+/// Tries each interpretation in order, returning the first that succeeds
+pub fn interpret_first(data: &[u8], order: &[Interpretation]) -> Option<(Interpretation, String)> {
+    order
+        .iter()
+        .find_map(|interpretation| Some((*interpretation, interpretation.interpret(data)?)))
+}
+
+/// Encodes bytes as standard, padded base64
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for triple in data.chunks(3) {
+        let mut group = [0u8; 4];
+        group[1..1 + triple.len()].copy_from_slice(triple);
+        let group = u32::from_be_bytes(group);
+
+        for i in 0..4 {
+            if i <= triple.len() {
+                let index = (group >> (18 - 6 * i)) & 0b11_1111;
+                text.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// Decodes standard, padded base64. Whitespace is ignored
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let symbols: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if symbols.is_empty() || !symbols.len().is_multiple_of(4) {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(symbols.len() / 4 * 3);
+    let quads = symbols.chunks(4).count();
+    for (i, quad) in symbols.chunks(4).enumerate() {
+        let padding = quad.iter().rev().take_while(|&&b| b == b'=').count();
+        if padding > 2 || (padding > 0 && i != quads - 1) {
+            return None;
+        }
+
+        let mut group: u32 = 0;
+        for &symbol in &quad[..4 - padding] {
+            let value = BASE64_ALPHABET.iter().position(|&b| b == symbol)?;
+            group = (group << 6) | value as u32;
+        }
+        group <<= 6 * padding as u32;
+
+        bytes.extend_from_slice(&group.to_be_bytes()[1..4 - padding]);
+    }
+
+    Some(bytes)
+}
+
+/// Encodes bytes as lowercase hexadecimal digit pairs
+pub fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes a string of hexadecimal digit pairs. Whitespace is ignored
+pub fn hex_decode(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"hi"), "aGk=");
+        assert_eq!(base64_encode(b"hello"), "aGVsbG8=");
+        assert_eq!(base64_encode(b"hello!"), "aGVsbG8h");
+    }
+
+    #[test]
+    fn test_base64_decode() {
+        assert_eq!(base64_decode("aGk=").unwrap(), b"hi");
+        assert_eq!(base64_decode("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(base64_decode("aGVsbG8h").unwrap(), b"hello!");
+        assert!(base64_decode("aGk").is_none());
+        assert!(base64_decode("aG=k").is_none());
+    }
+
+    #[test]
+    fn test_hex_decode() {
+        assert_eq!(hex_decode(&hex_encode(b"hi")).unwrap(), b"hi");
+        assert_eq!(hex_decode("6869").unwrap(), b"hi");
+        assert!(hex_decode("686").is_none());
+        assert!(hex_decode("zz").is_none());
+    }
+
+    #[test]
+    fn test_interpret_first_prefers_order() {
+        let data = b"aGVsbG8gd29ybGQ=";
+
+        let (interpretation, text) =
+            interpret_first(data, &[Interpretation::Base64, Interpretation::Utf8]).unwrap();
+        assert_eq!(interpretation, Interpretation::Base64);
+        assert_eq!(text, "hello world");
+
+        // Base64 text is also valid UTF-8, so listing utf8 first matches that instead
+        let (interpretation, text) =
+            interpret_first(data, &[Interpretation::Utf8, Interpretation::Base64]).unwrap();
+        assert_eq!(interpretation, Interpretation::Utf8);
+        assert_eq!(text, "aGVsbG8gd29ybGQ=");
+    }
+
+    #[test]
+    fn test_interpret_first_falls_through() {
+        let data = b"hello world";
+        let (interpretation, text) = interpret_first(
+            data,
+            &[
+                Interpretation::Base64,
+                Interpretation::Hex,
+                Interpretation::Utf8,
+            ],
+        )
+        .unwrap();
+        assert_eq!(interpretation, Interpretation::Utf8);
+        assert_eq!(text, "hello world");
+    }
+
+    #[test]
+    fn test_interpret_rejects_unprintable() {
+        // Valid base64 of bytes that aren't printable text
+        assert!(Interpretation::Base64.interpret(b"AAEC").is_none());
+        assert!(interpret_first(&[0xff, 0x00], &[Interpretation::Utf8]).is_none());
+    }
+}
@@ -0,0 +1,116 @@
+use crate::chunk::Chunk;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum IhdrError {
+    #[error("IHDR data is {0} bytes, expected 13")]
+    InvalidLength(usize),
+
+    #[error("{0} chunk is not IHDR")]
+    NotIhdr(String),
+}
+
+/// The fields of an IHDR chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IhdrData {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    pub compression_method: u8,
+    pub filter_method: u8,
+    pub interlace_method: u8,
+}
+
+impl TryFrom<&[u8]> for IhdrData {
+    type Error = IhdrError;
+
+    /// Parse the data of an IHDR chunk
+    fn try_from(data: &[u8]) -> Result<IhdrData, IhdrError> {
+        if data.len() != 13 {
+            return Err(IhdrError::InvalidLength(data.len()));
+        }
+
+        Ok(IhdrData {
+            width: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+            height: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            bit_depth: data[8],
+            color_type: data[9],
+            compression_method: data[10],
+            filter_method: data[11],
+            interlace_method: data[12],
+        })
+    }
+}
+
+impl TryFrom<&Chunk> for IhdrData {
+    type Error = IhdrError;
+
+    /// Parse an IHDR chunk, failing for chunks of any other type
+    fn try_from(chunk: &Chunk) -> Result<IhdrData, IhdrError> {
+        let chunk_type = chunk.chunk_type().to_string();
+        if chunk_type != "IHDR" {
+            return Err(IhdrError::NotIhdr(chunk_type));
+        }
+        IhdrData::try_from(chunk.data())
+    }
+}
+
+impl IhdrData {
+    /// Short name of the colour type, `None` for colour types the specification doesn't define
+    pub fn color_type_name(&self) -> Option<&'static str> {
+        match self.color_type {
+            0 => Some("Gray"),
+            2 => Some("RGB"),
+            3 => Some("Indexed"),
+            4 => Some("GrayA"),
+            6 => Some("RGBA"),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_ihdr_from_bytes() {
+        let data = [0, 0, 7, 128, 0, 0, 4, 56, 8, 6, 0, 0, 1];
+        let ihdr = IhdrData::try_from(&data[..]).unwrap();
+
+        assert_eq!((ihdr.width, ihdr.height), (1920, 1080));
+        assert_eq!(ihdr.bit_depth, 8);
+        assert_eq!(ihdr.color_type_name(), Some("RGBA"));
+        assert_eq!(ihdr.interlace_method, 1);
+    }
+
+    #[test]
+    fn test_ihdr_from_chunk() {
+        let data = vec![0, 0, 0, 50, 0, 0, 0, 40, 8, 2, 0, 0, 0];
+        let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), data);
+        let ihdr = IhdrData::try_from(&chunk).unwrap();
+        assert_eq!((ihdr.width, ihdr.height), (50, 40));
+
+        let chunk = Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![0; 13]);
+        assert!(matches!(
+            IhdrData::try_from(&chunk),
+            Err(IhdrError::NotIhdr(chunk_type)) if chunk_type == "IDAT"
+        ));
+        let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 14]);
+        assert!(matches!(
+            IhdrData::try_from(&chunk),
+            Err(IhdrError::InvalidLength(14))
+        ));
+    }
+
+    #[test]
+    fn test_ihdr_invalid_length() {
+        assert!(matches!(
+            IhdrData::try_from(&[0u8; 12][..]),
+            Err(IhdrError::InvalidLength(12))
+        ));
+    }
+}
@@ -2,25 +2,197 @@
 PNG encoding project from https://jrdngr.github.io/pngme_book/
 */
 mod args;
+mod canonical;
 mod chunk;
 mod chunk_type;
 mod commands;
+mod encoding;
+mod ihdr;
+mod palette;
+mod payload;
 mod png;
+mod rng;
+mod sha256;
+mod stdio;
+mod stego;
+mod text;
+mod timestamp;
+mod validate;
+mod zlib;
 use clap::Parser;
+use std::collections::HashSet;
+use std::process::ExitCode;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> ExitCode {
     let cli = args::Cli::parse();
 
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            if !cli.quiet_errors {
+                eprintln!("Error: {}", e);
+            }
+            match e.downcast_ref::<commands::CommandError>() {
+                Some(e) => e.exit_code(),
+                None => ExitCode::FAILURE,
+            }
+        }
+    }
+}
+
+fn run(cli: &args::Cli) -> Result<(), Box<dyn std::error::Error>> {
     match &cli.command {
         args::Commands::Encode {
             file,
             chunk_type,
+            source,
+            destination,
+            placement,
+            framing,
+            time_filter,
+            batch,
+        } => {
+            commands::check_batch_source(source, &batch.more_files)?;
+            let mut taken = HashSet::new();
+            commands::for_each_file(file, &batch.more_files, |file| {
+                let destination = match &batch.output_dir {
+                    Some(dir) => &args::EncodeDestination {
+                        output_file: Some(commands::path_in_dir(dir, file, &mut taken)?),
+                        stdout: false,
+                    },
+                    None => destination,
+                };
+                commands::encode(
+                    file,
+                    chunk_type,
+                    source,
+                    placement,
+                    framing,
+                    time_filter,
+                    destination,
+                )
+            })?
+        }
+        args::Commands::Decode {
+            file,
+            chunk_type,
+            type_bytes,
+            output,
+            fix_crc,
+            framing,
+            more_files,
+        } => commands::for_each_file(file, more_files, |file| {
+            if !more_files.is_empty() {
+                println!("{}:", file);
+            }
+            commands::decode(file, chunk_type, *type_bytes, output, *fix_crc, framing)
+        })?,
+        args::Commands::Embed {
+            file,
             message,
             output_file,
-        } => commands::encode(file, chunk_type, message, output_file)?,
-        args::Commands::Decode { file, chunk_type } => commands::decode(file, chunk_type)?,
-        args::Commands::Remove { file, chunk_type } => commands::remove(file, chunk_type)?,
-        args::Commands::Print { file } => commands::print(file)?,
+        } => commands::embed(file, message, output_file)?,
+        args::Commands::Reveal { file } => commands::reveal(file)?,
+        args::Commands::Insert {
+            file,
+            chunk_type,
+            data_file,
+            index,
+            output_file,
+        } => commands::insert(file, chunk_type, data_file, *index, output_file)?,
+        args::Commands::Remove {
+            file,
+            chunk_type,
+            force,
+            all,
+            nth,
+            batch,
+        } => {
+            let mut taken = HashSet::new();
+            commands::for_each_file(file, &batch.more_files, |file| {
+                let output_file = match &batch.output_dir {
+                    Some(dir) => Some(commands::path_in_dir(dir, file, &mut taken)?),
+                    None => None,
+                };
+                commands::remove(file, chunk_type, *force, *all, *nth, &output_file)
+            })?
+        }
+        args::Commands::Strip {
+            file,
+            output_file,
+            keep,
+        } => commands::strip(file, keep, output_file)?,
+        args::Commands::Rechunk {
+            file,
+            max_idat,
+            merge_idat,
+            output_file,
+        } => commands::rechunk(file, *max_idat, *merge_idat, output_file)?,
+        args::Commands::Canonicalize {
+            file,
+            output_file,
+            steps,
+        } => commands::canonicalize(file, steps, output_file)?,
+        args::Commands::Print {
+            file,
+            canonical,
+            fix_crc,
+            describe,
+            all_streams,
+            filter,
+        } => commands::print(file, *canonical, *fix_crc, *describe, *all_streams, filter)?,
+        args::Commands::Audit { file } => commands::audit(file)?,
+        args::Commands::Fix { file, output_file } => commands::fix(file, output_file)?,
+        args::Commands::Verify { file, strict } => commands::verify(file, *strict)?,
+        args::Commands::List { file } => commands::list(file)?,
+        args::Commands::Stat { file } => commands::stat(file)?,
+        args::Commands::Lint { file } => commands::lint(file)?,
+        args::Commands::Info {
+            file,
+            palette,
+            producer,
+        } => commands::info(file, *palette, *producer)?,
+        args::Commands::Migrate {
+            file,
+            chunk_type,
+            output_file,
+            keyword,
+        } => commands::migrate(file, chunk_type, keyword, output_file)?,
+        args::Commands::Text { file } => commands::text(file)?,
+        args::Commands::Meta { action } => match action {
+            args::MetaAction::Set {
+                file,
+                entries,
+                output_file,
+            } => commands::meta_set(file, entries, output_file)?,
+            args::MetaAction::Get { file, keyword } => commands::meta_get(file, keyword)?,
+            args::MetaAction::Unset {
+                file,
+                keyword,
+                output_file,
+            } => commands::meta_unset(file, keyword, output_file)?,
+        },
+        args::Commands::Extract {
+            file,
+            chunk_type,
+            output_file,
+            decompress,
+            raw,
+            nth,
+            all,
+            concat: _,
+        } => commands::extract(file, chunk_type, output_file, *decompress, *raw, *nth, *all)?,
+        args::Commands::Icc { file, output_file } => commands::icc(file, output_file)?,
+        args::Commands::Manifest { dir, check } => match check {
+            Some(manifest) => commands::check_manifest(manifest)?,
+            None => commands::manifest(dir.as_deref().unwrap_or("."))?,
+        },
+        args::Commands::VerifyData {
+            file,
+            chunk_type,
+            expected_file,
+        } => commands::verify_data(file, chunk_type, expected_file)?,
+        args::Commands::Import { file, output_file } => commands::import(file, output_file)?,
     }
 
     Ok(())
@@ -0,0 +1,107 @@
+use crate::ihdr::IhdrData;
+use crate::png::Png;
+use std::fmt;
+
+/// Colour type of images whose pixels are palette indices
+const INDEXED_COLOR: u8 = 3;
+
+/// Number of colors in a PNG's palette and how many of them are not fully opaque. A palette in
+/// an image that isn't indexed is only a suggestion for viewers that can show few colours
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaletteSummary {
+    pub colors: usize,
+    pub with_alpha: usize,
+    pub suggested: bool,
+}
+
+impl fmt::Display for PaletteSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.suggested {
+            return write!(f, "suggested, {} colors", self.colors);
+        }
+        write!(f, "{} colors, {} with alpha", self.colors, self.with_alpha)
+    }
+}
+
+/// Summarises the PLTE chunk, using tRNS to count the entries with transparency. `None` if the
+/// PNG has no palette
+pub fn palette_summary(png: &Png) -> Option<PaletteSummary> {
+    let colors = png.chunk_by_type("PLTE")?.data().len() / 3;
+    let suggested = png
+        .chunk_by_type("IHDR")
+        .and_then(|ihdr| IhdrData::try_from(ihdr).ok())
+        .is_some_and(|ihdr| ihdr.color_type != INDEXED_COLOR);
+    if suggested {
+        // tRNS then holds a single transparent colour rather than alpha for palette entries
+        return Some(PaletteSummary {
+            colors,
+            with_alpha: 0,
+            suggested,
+        });
+    }
+
+    // tRNS holds one alpha value per palette entry, entries past its end are fully opaque
+    let with_alpha = png.chunk_by_type("tRNS").map_or(0, |trns| {
+        trns.data()
+            .iter()
+            .take(colors)
+            .filter(|&&alpha| alpha < 255)
+            .count()
+    });
+
+    Some(PaletteSummary {
+        colors,
+        with_alpha,
+        suggested,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_palette_summary() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 3, 0, 0, 0]),
+            chunk("PLTE", &[0; 12]),
+            chunk("tRNS", &[0, 128, 255]),
+            chunk("IDAT", &[]),
+            chunk("IEND", &[]),
+        ]);
+
+        let summary = palette_summary(&png).unwrap();
+        assert_eq!(summary.colors, 4);
+        assert_eq!(summary.with_alpha, 2);
+        assert_eq!(summary.to_string(), "4 colors, 2 with alpha");
+        assert!(!summary.suggested);
+    }
+
+    #[test]
+    fn test_palette_summary_truecolor_is_suggested() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0]),
+            chunk("PLTE", &[0; 6]),
+            chunk("tRNS", &[0, 0, 0, 0, 0, 0]),
+            chunk("IDAT", &[]),
+            chunk("IEND", &[]),
+        ]);
+
+        let summary = palette_summary(&png).unwrap();
+        assert!(summary.suggested);
+        assert_eq!(summary.with_alpha, 0);
+        assert_eq!(summary.to_string(), "suggested, 2 colors");
+    }
+
+    #[test]
+    fn test_palette_summary_without_palette() {
+        assert_eq!(palette_summary(&Png::default()), None);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::rng::SplitMix64;
+use crate::sha256::sha256;
+use thiserror::Error;
+
+/// Marks the start of the hash trailer: the message is followed by this marker and the SHA-256
+/// digest of the message
+const HASH_MARKER: [u8; 4] = *b"SHA2";
+const TRAILER_LENGTH: usize = HASH_MARKER.len() + 32;
+
+/// Padded data starts with the length of the message as a 4-byte big-endian number, followed by
+/// the message and then random bytes up to the padded size
+const LENGTH_PREFIX: usize = 4;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PayloadError {
+    #[error("chunk data has no integrity hash")]
+    MissingHash(),
+
+    #[error("integrity hash does not match the message")]
+    HashMismatch(),
+
+    #[error("message is {length} bytes, too long to pad to {size} bytes")]
+    TooLongToPad { length: usize, size: usize },
+
+    #[error("padded data is {0} bytes, too short for the length it records")]
+    InvalidPadding(usize),
+}
+
+/// Appends the hash trailer to a message
+pub fn append_hash(message: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(message.len() + TRAILER_LENGTH);
+    data.extend_from_slice(message);
+    data.extend(HASH_MARKER);
+    data.extend(sha256(message));
+    data
+}
+
+/// Splits chunk data into the message and the digest from its hash trailer
+pub fn split_hash(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let message_length = data.len().checked_sub(TRAILER_LENGTH)?;
+    let (message, trailer) = data.split_at(message_length);
+    let digest = trailer.strip_prefix(&HASH_MARKER)?;
+    Some((message, digest))
+}
+
+/// Returns the message from chunk data with a hash trailer, checking that the hash matches
+pub fn verify_hash(data: &[u8]) -> Result<&[u8], PayloadError> {
+    let (message, digest) = split_hash(data).ok_or(PayloadError::MissingHash())?;
+    if sha256(message) == digest {
+        Ok(message)
+    } else {
+        Err(PayloadError::HashMismatch())
+    }
+}
+
+/// Frames a message as exactly `size` bytes: a length prefix, the message and padding bytes from
+/// `rng`
+pub fn pad(message: &[u8], size: usize, rng: &mut SplitMix64) -> Result<Vec<u8>, PayloadError> {
+    let too_long = PayloadError::TooLongToPad {
+        length: message.len(),
+        size,
+    };
+    if message.len() + LENGTH_PREFIX > size {
+        return Err(too_long);
+    }
+    let length = u32::try_from(message.len()).map_err(|_| too_long)?;
+
+    let mut data = Vec::with_capacity(size);
+    data.extend(length.to_be_bytes());
+    data.extend_from_slice(message);
+    let padding_start = data.len();
+    data.resize(size, 0);
+    rng.fill_bytes(&mut data[padding_start..]);
+    Ok(data)
+}
+
+/// Returns the message from data framed by `pad`
+pub fn unpad(data: &[u8]) -> Result<&[u8], PayloadError> {
+    let invalid = || PayloadError::InvalidPadding(data.len());
+    let (prefix, rest) = data.split_at_checked(LENGTH_PREFIX).ok_or_else(invalid)?;
+    let length = u32::from_be_bytes(prefix.try_into().unwrap()) as usize;
+    rest.get(..length).ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_round_trip() {
+        let data = append_hash(b"hidden message");
+        assert_eq!(data.len(), 14 + TRAILER_LENGTH);
+        assert_eq!(verify_hash(&data), Ok(&b"hidden message"[..]));
+
+        assert_eq!(verify_hash(&append_hash(b"")), Ok(&b""[..]));
+    }
+
+    #[test]
+    fn test_tampered_payload() {
+        let mut data = append_hash(b"hidden message");
+        data[0] ^= 0x01;
+        assert_eq!(verify_hash(&data), Err(PayloadError::HashMismatch()));
+    }
+
+    #[test]
+    fn test_padding_round_trip() {
+        let data = pad(b"short message", 256, &mut SplitMix64::new(1)).unwrap();
+        assert_eq!(data.len(), 256);
+        assert_eq!(unpad(&data), Ok(&b"short message"[..]));
+
+        // Padding can hold a hash trailer too
+        let data = pad(&append_hash(b"short message"), 256, &mut SplitMix64::new(1)).unwrap();
+        assert_eq!(
+            verify_hash(unpad(&data).unwrap()),
+            Ok(&b"short message"[..])
+        );
+    }
+
+    #[test]
+    fn test_padding_is_reproducible_with_seed() {
+        let first = pad(b"short message", 64, &mut SplitMix64::new(42)).unwrap();
+        let second = pad(b"short message", 64, &mut SplitMix64::new(42)).unwrap();
+        let other = pad(b"short message", 64, &mut SplitMix64::new(43)).unwrap();
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn test_padding_errors() {
+        let mut rng = SplitMix64::new(1);
+        assert_eq!(
+            pad(&[0; 253], 256, &mut rng),
+            Err(PayloadError::TooLongToPad {
+                length: 253,
+                size: 256
+            })
+        );
+        assert!(pad(&[0; 252], 256, &mut rng).is_ok());
+        assert_eq!(unpad(&[0, 0, 1]), Err(PayloadError::InvalidPadding(3)));
+        assert_eq!(
+            unpad(&[0, 0, 0, 9, 1]),
+            Err(PayloadError::InvalidPadding(5))
+        );
+    }
+
+    #[test]
+    fn test_missing_hash() {
+        assert_eq!(
+            verify_hash(b"hidden message"),
+            Err(PayloadError::MissingHash())
+        );
+    }
+}
@@ -1,7 +1,12 @@
 use crate::chunk::Chunk;
 use crate::chunk_type::ChunkType;
+use crate::sha256::sha256;
+use crate::validate::{self, Placement, ValidationIssue};
+use std::collections::{BTreeSet, HashSet};
+use std::ops::Range;
+use std::path::Path;
 use std::str::FromStr;
-use std::{fmt, mem, str, vec};
+use std::{fmt, fs, io, mem, str, vec};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -9,11 +14,30 @@ pub enum PngError {
     #[error("cannot parse bytes: {reason}")]
     InvalidBytes { reason: String },
 
-    #[error("invalid header for PNG file")]
-    InvalidHeader(),
+    #[error("invalid PNG signature, the file is not a PNG")]
+    InvalidSignature(),
 
     #[error("chunk does not exist")]
     ChunkNotFound(),
+
+    #[error("cannot insert a chunk at index {0}, it must go after IHDR and before IEND")]
+    InvalidInsertIndex(usize),
+
+    #[error("cannot insert a chunk at index {index}, the file has {count} chunks")]
+    InsertIndexOutOfRange { index: usize, count: usize },
+
+    #[error("{0}")]
+    Io(#[from] io::Error),
+
+    #[error("merged IDAT data would be longer than a chunk can hold ({max} bytes)", max = u32::MAX)]
+    MergedIdatTooLong(),
+
+    #[error("no {chunk_type} chunk at occurrence {n}, the file has {count}")]
+    OccurrenceOutOfRange {
+        chunk_type: String,
+        n: usize,
+        count: usize,
+    },
 }
 
 #[derive(Debug)]
@@ -27,6 +51,57 @@ impl TryFrom<&[u8]> for Png {
 
     /// Construct a Png from a byte slice (including the standard png header)
     fn try_from(value: &[u8]) -> Result<Png, PngError> {
+        Png::parse(value, false, false).map(|(png, _)| png)
+    }
+}
+
+impl Png {
+    /// Construct a Png like `try_from`, but accept chunks with an incorrect CRC and any 8-byte
+    /// signature, which `as_bytes` writes back unchanged. Use `Chunk::is_crc_valid` and
+    /// `signature` to find the problems
+    pub fn try_from_lenient(value: &[u8]) -> Result<Png, PngError> {
+        Png::parse(value, true, false).map(|(png, _)| png)
+    }
+
+    /// Read and parse a PNG file like `try_from`
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Png, PngError> {
+        Png::try_from(&fs::read(path)?[..])
+    }
+
+    /// Write the PNG to a file, replacing anything already there
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<(), PngError> {
+        fs::write(path, self.as_bytes())?;
+        Ok(())
+    }
+
+    /// Construct one Png for each PNG stream in the bytes, where each stream after the first
+    /// starts with a PNG signature right after the previous stream's IEND chunk. Any other trailing
+    /// data is ignored. With `lenient`, the first stream is parsed like `try_from_lenient`
+    pub fn try_from_streams(value: &[u8], lenient: bool) -> Result<Vec<Png>, PngError> {
+        let mut streams = vec![];
+        let mut rest = value;
+        loop {
+            let (png, consumed) = Png::parse(rest, lenient, true)?;
+            streams.push(png);
+
+            rest = &rest[consumed..];
+            if !rest.starts_with(&Png::STANDARD_HEADER) {
+                return Ok(streams);
+            }
+        }
+    }
+
+    /// Construct a Png like `try_from_lenient`, but stop at the first IEND chunk and also return
+    /// the bytes that follow it
+    pub fn try_from_lenient_until_iend(value: &[u8]) -> Result<(Png, &[u8]), PngError> {
+        let (png, consumed) = Png::parse(value, true, true)?;
+        Ok((png, &value[consumed..]))
+    }
+
+    /// Parses a signature followed by chunks, returning the number of bytes used. `lenient` is as
+    /// for `try_from_lenient`. With `stop_after_iend`, parsing ends at the first IEND chunk instead
+    /// of the end of the bytes
+    fn parse(value: &[u8], lenient: bool, stop_after_iend: bool) -> Result<(Png, usize), PngError> {
         if value.len() < Png::STANDARD_HEADER.len() {
             return Err(PngError::InvalidBytes {
                 reason: format!(
@@ -37,48 +112,88 @@ impl TryFrom<&[u8]> for Png {
         }
 
         // Check header is correct
-        if value[..Png::STANDARD_HEADER.len()] != Png::STANDARD_HEADER {
-            return Err(PngError::InvalidHeader {});
+        let mut header = [0u8; 8];
+        header.copy_from_slice(&value[..Png::STANDARD_HEADER.len()]);
+        if header != Png::STANDARD_HEADER && !lenient {
+            return Err(PngError::InvalidSignature());
         }
 
         let mut chunks = vec![];
         let mut cursor = Png::STANDARD_HEADER.len();
-        loop {
-            // Read the chunk data length from the first 4 bytes
-            let length_bytes_range = cursor..cursor + mem::size_of::<u32>();
-            let mut length_bytes = [0u8; mem::size_of::<u32>()];
-            length_bytes.copy_from_slice(&value[length_bytes_range]);
-            let length = u32::from_be_bytes(length_bytes) as usize;
-
-            // Read the whole range of bytes for the chunk
-
-            let chunk_length = mem::size_of::<u32>()
-                + mem::size_of::<ChunkType>()
-                + length
-                + mem::size_of::<u32>();
-            let chunk_bytes_range = cursor..cursor + chunk_length;
-            let chunk_bytes = &value[chunk_bytes_range];
-
-            chunks.push(match Chunk::try_from(chunk_bytes) {
-                Ok(v) => v,
-                Err(e) => {
-                    return Err(PngError::InvalidBytes {
-                        reason: e.to_string(),
-                    })
-                }
-            });
+        while cursor < value.len() {
+            // The chunk's length field says how many of the remaining bytes belong to it
+            let chunk_bytes = &value[cursor..];
+            let chunk = if lenient {
+                Chunk::try_from_lenient(chunk_bytes)
+            } else {
+                Chunk::try_from(chunk_bytes)
+            }
+            .map_err(|e| PngError::InvalidBytes {
+                reason: e.to_string(),
+            })?;
 
             // Move the cursor to the next chunk
-            cursor += chunk_length;
-            if cursor >= value.len() {
+            cursor += 12 + chunk.data().len();
+            let at_iend = chunk.chunk_type().bytes() == *b"IEND";
+            chunks.push(chunk);
+            if stop_after_iend && at_iend {
                 break;
             }
         }
 
-        Ok(Png {
-            header: Png::STANDARD_HEADER,
-            chunks,
-        })
+        Ok((Png { header, chunks }, cursor))
+    }
+}
+
+const IDAT: [u8; 4] = *b"IDAT";
+
+/// Position of a chunk type in the order `Png::normalize_order` sorts by, or `None` for chunks
+/// whose placement the PNG specification doesn't restrict
+fn canonical_rank(chunk_type: &ChunkType) -> Option<u8> {
+    match &chunk_type.bytes() {
+        b"IHDR" => Some(0),
+        b"PLTE" => Some(2),
+        &IDAT => Some(4),
+        b"IEND" => Some(5),
+        _ => match validate::ancillary_placement(&chunk_type.to_string())? {
+            Placement::BeforePalette => Some(1),
+            _ => Some(3),
+        },
+    }
+}
+
+/// Joins chunks of the same type into one chunk holding all of their data
+fn merge_chunks(mut chunks: Vec<Chunk>) -> Option<Chunk> {
+    if chunks.len() <= 1 {
+        return chunks.pop();
+    }
+
+    let data = chunks
+        .iter()
+        .flat_map(|chunk| chunk.data())
+        .copied()
+        .collect();
+    Some(Chunk::new(chunks[0].chunk_type().clone(), data))
+}
+
+/// IHDR data for a 1x1, 8-bit greyscale, non-interlaced image
+const MINIMAL_IHDR_DATA: [u8; 13] = [0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0];
+
+/// zlib stream holding a single scanline: filter type 0 followed by one black pixel
+const MINIMAL_IDAT_DATA: [u8; 10] = [120, 156, 99, 96, 0, 0, 0, 2, 0, 1];
+
+impl Default for Png {
+    /// Construct the smallest valid PNG: a single black pixel
+    fn default() -> Self {
+        let chunk = |chunk_type: &[u8; 4], data: &[u8]| {
+            Chunk::new(ChunkType::try_from(*chunk_type).unwrap(), data.to_vec())
+        };
+
+        Png::from_chunks(vec![
+            chunk(b"IHDR", &MINIMAL_IHDR_DATA),
+            chunk(b"IDAT", &MINIMAL_IDAT_DATA),
+            chunk(b"IEND", &[]),
+        ])
     }
 }
 
@@ -99,10 +214,62 @@ impl Png {
         }
     }
 
+    /// Construct the smallest valid PNG, as for `default`, carrying `data` in a chunk of the given
+    /// type right before IEND
+    pub fn wrap_data(data: &[u8], chunk_type: ChunkType) -> Png {
+        let mut png = Png::default();
+        png.insert_chunk_before_iend(Chunk::new(chunk_type, data.to_vec()));
+        png
+    }
+
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.push(chunk);
     }
 
+    /// Inserts a chunk so that it ends up at `index`. The index must be after IHDR and no later
+    /// than IEND, so the chunk lands between them
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) -> Result<(), PngError> {
+        let position = |chunk_type: &[u8; 4]| {
+            self.chunks
+                .iter()
+                .position(|x| x.chunk_type().bytes() == *chunk_type)
+        };
+        let after_ihdr = position(b"IHDR").is_none_or(|ihdr| index > ihdr);
+        let before_iend = position(b"IEND").is_none_or(|iend| index <= iend);
+        if index > self.chunks.len() || !after_ihdr || !before_iend {
+            return Err(PngError::InvalidInsertIndex(index));
+        }
+
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
+
+    /// Inserts a chunk so that it ends up at `index`, wherever that is. Unlike `insert_chunk`, it may
+    /// go before IHDR or after IEND, leaving the file invalid
+    pub fn insert_chunk_at(&mut self, index: usize, chunk: Chunk) -> Result<(), PngError> {
+        if index > self.chunks.len() {
+            return Err(PngError::InsertIndexOutOfRange {
+                index,
+                count: self.chunks.len(),
+            });
+        }
+
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
+
+    /// Inserts a chunk right before IEND, or at the end if there is no IEND chunk
+    pub fn insert_chunk_before_iend(&mut self, chunk: Chunk) {
+        let iend = self
+            .chunks
+            .iter()
+            .position(|x| x.chunk_type().bytes() == *b"IEND");
+        match iend {
+            Some(index) => self.chunks.insert(index, chunk),
+            None => self.chunks.push(chunk),
+        }
+    }
+
     /// Removes the first occurance of a given chunk type
     pub fn remove_first_chunk(&mut self, chunk_type: &str) -> Result<Chunk, PngError> {
         let looking_for = match ChunkType::from_str(chunk_type) {
@@ -122,14 +289,207 @@ impl Png {
         Ok(self.chunks.remove(index))
     }
 
-    pub fn header(&self) -> &[u8; 8] {
+    /// Removes the occurance of a given chunk type at 0-based position `n` among chunks of that type
+    pub fn remove_nth_chunk(&mut self, chunk_type: &str, n: usize) -> Result<Chunk, PngError> {
+        let looking_for = ChunkType::from_str(chunk_type).map_err(|_| PngError::ChunkNotFound())?;
+        let indices: Vec<usize> = (0..self.chunks.len())
+            .filter(|&i| self.chunks[i].chunk_type() == &looking_for)
+            .collect();
+        let index = indices
+            .get(n)
+            .ok_or_else(|| PngError::OccurrenceOutOfRange {
+                chunk_type: chunk_type.to_string(),
+                n,
+                count: indices.len(),
+            })?;
+        Ok(self.chunks.remove(*index))
+    }
+
+    /// Removes every chunk of a given type, returning them in their original order
+    pub fn remove_all_chunks(&mut self, chunk_type: &str) -> Result<Vec<Chunk>, PngError> {
+        let looking_for = ChunkType::from_str(chunk_type).map_err(|_| PngError::ChunkNotFound())?;
+        let removed = self.retain(|chunk| chunk.chunk_type() != &looking_for);
+        if removed.is_empty() {
+            return Err(PngError::ChunkNotFound());
+        }
+        Ok(removed)
+    }
+
+    /// Inserts a chunk right after the first occurance of a given chunk type
+    pub fn insert_after_type(&mut self, after: &str, chunk: Chunk) -> Result<(), PngError> {
+        let looking_for = ChunkType::from_str(after).map_err(|_| PngError::ChunkNotFound())?;
+        let index = self
+            .chunks
+            .iter()
+            .position(|x| x.chunk_type() == &looking_for)
+            .ok_or(PngError::ChunkNotFound())?;
+        self.chunks.insert(index + 1, chunk);
+        Ok(())
+    }
+
+    /// Inserts a chunk right before the first occurance of a given chunk type
+    pub fn insert_before_type(&mut self, before: &str, chunk: Chunk) -> Result<(), PngError> {
+        let looking_for = ChunkType::from_str(before).map_err(|_| PngError::ChunkNotFound())?;
+        let index = self
+            .chunks
+            .iter()
+            .position(|x| x.chunk_type() == &looking_for)
+            .ok_or(PngError::ChunkNotFound())?;
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
+
+    /// Replaces the chunk at an index, returning the chunk that was replaced
+    pub fn replace_chunk(&mut self, index: usize, replacement: Chunk) -> Option<Chunk> {
+        let chunk = self.chunks.get_mut(index)?;
+        Some(mem::replace(chunk, replacement))
+    }
+
+    /// Replaces the first occurance of a given chunk type with another chunk in the same position,
+    /// returning the chunk that was replaced
+    pub fn replace_first_chunk(
+        &mut self,
+        chunk_type: &str,
+        replacement: Chunk,
+    ) -> Result<Chunk, PngError> {
+        let looking_for = ChunkType::from_str(chunk_type).map_err(|_| PngError::ChunkNotFound())?;
+        let chunk = self
+            .chunks
+            .iter_mut()
+            .find(|x| x.chunk_type() == &looking_for)
+            .ok_or(PngError::ChunkNotFound())?;
+        Ok(mem::replace(chunk, replacement))
+    }
+
+    /// Recalculates the CRC of every chunk whose supplied CRC is incorrect, returning how many
+    /// were fixed
+    pub fn recalculate_crcs(&mut self) -> usize {
+        let mut fixed = 0;
+        for chunk in self.chunks.iter_mut().filter(|chunk| !chunk.is_crc_valid()) {
+            chunk.recalculate_crc();
+            fixed += 1;
+        }
+        fixed
+    }
+
+    /// Keeps only the chunks for which `keep` returns true, like `Vec::retain`, and returns the
+    /// removed chunks in their original order. Critical chunks aren't protected, so callers must
+    /// take care not to remove chunks the image needs
+    pub fn retain(&mut self, mut keep: impl FnMut(&Chunk) -> bool) -> Vec<Chunk> {
+        let (kept, removed) = mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|chunk| keep(chunk));
+        self.chunks = kept;
+        removed
+    }
+
+    /// Splits every IDAT chunk with more than `max` bytes of data into consecutive IDAT chunks of
+    /// at most `max` bytes. The image data is one continuous stream, so the split can fall anywhere
+    ///
+    /// Panics if `max` is 0
+    pub fn rechunk_idat(&mut self, max: usize) {
+        assert!(max > 0, "IDAT chunks must hold at least 1 byte");
+
+        self.chunks = mem::take(&mut self.chunks)
+            .into_iter()
+            .flat_map(|chunk| {
+                if chunk.chunk_type().bytes() == IDAT && chunk.data().len() > max {
+                    chunk
+                        .data()
+                        .chunks(max)
+                        .map(|part| Chunk::new(chunk.chunk_type().clone(), part.to_vec()))
+                        .collect()
+                } else {
+                    vec![chunk]
+                }
+            })
+            .collect();
+    }
+
+    /// Replaces each run of consecutive IDAT chunks with a single IDAT chunk holding their
+    /// concatenated data. IDAT chunks separated by other chunks are merged only within their runs.
+    /// Nothing changes if a run holds more data than fits in one chunk
+    pub fn merge_idat(&mut self) -> Result<(), PngError> {
+        // Check every run before taking the chunks apart
+        let mut run_length = 0u32;
+        for chunk in &self.chunks {
+            run_length = if chunk.chunk_type().bytes() == IDAT {
+                run_length
+                    .checked_add(chunk.length())
+                    .ok_or(PngError::MergedIdatTooLong())?
+            } else {
+                0
+            };
+        }
+
+        let mut chunks = Vec::with_capacity(self.chunks.len());
+        let mut run = vec![];
+        for chunk in mem::take(&mut self.chunks) {
+            if chunk.chunk_type().bytes() == IDAT {
+                run.push(chunk);
+            } else {
+                chunks.extend(merge_chunks(mem::take(&mut run)));
+                chunks.push(chunk);
+            }
+        }
+        chunks.extend(merge_chunks(run));
+        self.chunks = chunks;
+        Ok(())
+    }
+
+    /// Stably sorts the chunks into the order the PNG specification describes: IHDR, ancillary
+    /// chunks that must come before PLTE, PLTE, ancillary chunks that must come before IDAT, IDAT
+    /// and then IEND. Chunks of the same rank keep their order, so the image data is unchanged.
+    /// Any other chunk, such as an unknown or APNG chunk, may depend on where it is and is never
+    /// moved past a chunk that came before it. Returns whether any chunk moved
+    pub fn normalize_order(&mut self) -> bool {
+        let mut highest = 0;
+        let ranks: Vec<u8> = self
+            .chunks
+            .iter()
+            .map(|chunk| {
+                let rank = canonical_rank(chunk.chunk_type()).unwrap_or(highest);
+                highest = highest.max(rank);
+                rank
+            })
+            .collect();
+        if ranks.is_sorted() {
+            return false;
+        }
+
+        let mut ranked: Vec<(u8, Chunk)> =
+            ranks.into_iter().zip(mem::take(&mut self.chunks)).collect();
+        ranked.sort_by_key(|(rank, _)| *rank);
+        self.chunks = ranked.into_iter().map(|(_, chunk)| chunk).collect();
+        true
+    }
+
+    /// Removes each ancillary chunk whose type and data are identical to an earlier chunk's,
+    /// returning how many were removed
+    pub fn remove_duplicate_ancillary(&mut self) -> usize {
+        let mut seen = HashSet::new();
+        self.retain(|chunk| {
+            chunk.chunk_type().is_critical()
+                || seen.insert((chunk.chunk_type().bytes(), chunk.data().to_vec()))
+        })
+        .len()
+    }
+
+    /// The 8 bytes the file started with, which are only non-standard if parsed leniently
+    pub fn signature(&self) -> &[u8; 8] {
         &self.header
     }
 
+    /// Read-only view of every chunk, in file order
     pub fn chunks(&self) -> &[Chunk] {
         &self.chunks
     }
 
+    /// Iterates over every chunk, in file order
+    pub fn iter(&self) -> std::slice::Iter<'_, Chunk> {
+        self.chunks.iter()
+    }
+
     // Returns the first occurance of a given chunk type
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
         let looking_for = match ChunkType::from_str(chunk_type) {
@@ -140,6 +500,84 @@ impl Png {
         self.chunks.iter().find(|x| x.chunk_type() == &looking_for)
     }
 
+    /// Every chunk of a given type, in file order
+    pub fn chunks_by_type<'a>(&'a self, chunk_type: &str) -> impl Iterator<Item = &'a Chunk> {
+        let looking_for = ChunkType::from_str(chunk_type).ok();
+        self.chunks
+            .iter()
+            .filter(move |x| Some(x.chunk_type()) == looking_for.as_ref())
+    }
+
+    /// The occurrence of a given chunk type at 0-based position `n` among chunks of that type
+    pub fn nth_chunk_by_type(&self, chunk_type: &str, n: usize) -> Result<&Chunk, PngError> {
+        let chunks: Vec<&Chunk> = self.chunks_by_type(chunk_type).collect();
+        chunks
+            .get(n)
+            .copied()
+            .ok_or_else(|| PngError::OccurrenceOutOfRange {
+                chunk_type: chunk_type.to_string(),
+                n,
+                count: chunks.len(),
+            })
+    }
+
+    /// Returns the first chunk whose type is exactly the given bytes, even if they aren't letters
+    pub fn chunk_by_type_bytes(&self, bytes: [u8; 4]) -> Option<&Chunk> {
+        self.chunks.iter().find(|x| x.chunk_type().bytes() == bytes)
+    }
+
+    /// SHA-256 of the critical chunks' types and data, which changes when the image itself changes
+    /// but not when ancillary chunks such as tEXt are edited. Consecutive IDAT chunks are hashed as
+    /// one, so splitting or merging them doesn't change the fingerprint
+    pub fn critical_fingerprint(&self) -> Result<[u8; 32], PngError> {
+        let mut critical = Png::from_chunks(
+            self.chunks
+                .iter()
+                .filter(|chunk| chunk.chunk_type().is_critical())
+                .map(|chunk| Chunk::new(chunk.chunk_type().clone(), chunk.data().to_vec()))
+                .collect(),
+        );
+        critical.merge_idat()?;
+
+        let mut bytes = vec![];
+        for chunk in critical.chunks() {
+            bytes.extend(chunk.length().to_be_bytes());
+            bytes.extend(chunk.chunk_type().bytes());
+            bytes.extend(chunk.data());
+        }
+        Ok(sha256(&bytes))
+    }
+
+    /// The distinct chunk types present, in sorted order
+    pub fn chunk_type_set(&self) -> BTreeSet<String> {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    /// Checks the signature, every chunk's CRC and the ordering of the critical chunks, collecting
+    /// all of the problems rather than stopping at the first
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = validate::validate_signature(&self.header);
+        issues.extend(validate::validate_structure(self));
+        issues.extend(validate::validate_crcs(self));
+        issues.extend(validate::validate_chunk_types(self));
+        issues
+    }
+
+    /// Returns the `[start, end)` offsets of a chunk within `as_bytes`, covering its length, type,
+    /// data and CRC fields
+    pub fn byte_range_of_chunk(&self, index: usize) -> Option<Range<usize>> {
+        let chunk = self.chunks.get(index)?;
+        let start = self.header.len()
+            + self.chunks[..index]
+                .iter()
+                .map(|chunk| 12 + chunk.data().len())
+                .sum::<usize>();
+        Some(start..start + 12 + chunk.data().len())
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = self.header.to_vec();
         bytes.extend(
@@ -209,6 +647,17 @@ mod tests {
         assert!(png.is_ok());
     }
 
+    #[test]
+    fn test_lenient_keeps_non_standard_signature() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[..8].copy_from_slice(b"\x89PNG\n\r\x1a\n");
+        assert!(Png::try_from(&bytes[..]).is_err());
+
+        let png = Png::try_from_lenient(&bytes).unwrap();
+        assert_eq!(png.signature(), b"\x89PNG\n\r\x1a\n");
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
     #[test]
     fn test_invalid_header() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -227,6 +676,56 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_jpeg_is_invalid_signature() {
+        let mut bytes = vec![0xff, 0xd8, 0xff, 0xe0, 0, 16, b'J', b'F', b'I', b'F', 0, 1];
+        bytes.extend(testing_chunks().iter().flat_map(|chunk| chunk.as_bytes()));
+
+        assert!(matches!(
+            Png::try_from(bytes.as_ref()),
+            Err(PngError::InvalidSignature())
+        ));
+    }
+
+    #[test]
+    fn test_validate_collects_every_issue() {
+        assert!(Png::try_from(&PNG_FILE[..]).unwrap().validate().is_empty());
+
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[0] = 0;
+        bytes[61] ^= 0xff;
+        let png = Png::try_from_lenient(&bytes).unwrap();
+        let codes: Vec<&str> = png.validate().iter().map(|issue| issue.code()).collect();
+        assert_eq!(codes, ["PNG009", "PNG002"]);
+    }
+
+    #[test]
+    fn test_recalculate_crcs() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[61] ^= 0xff;
+        let mut png = Png::try_from_lenient(&bytes).unwrap();
+
+        assert_eq!(png.recalculate_crcs(), 1);
+        assert_eq!(png.as_bytes(), PNG_FILE);
+        assert_eq!(png.recalculate_crcs(), 0);
+    }
+
+    #[test]
+    fn test_truncated_png_is_error() {
+        for length in [9, 20, 40, PNG_FILE.len() - 1] {
+            assert!(Png::try_from_lenient(&PNG_FILE[..length]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_try_from_lenient_until_iend() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend(b"junk");
+        let (png, trailing) = Png::try_from_lenient_until_iend(&bytes).unwrap();
+        assert_eq!(png.chunks().len(), 7);
+        assert_eq!(trailing, b"junk");
+    }
+
     #[test]
     fn test_invalid_chunk() {
         let mut chunk_bytes: Vec<u8> = testing_chunks()
@@ -249,6 +748,24 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_lenient_accepts_invalid_crc() {
+        let mut bytes = PNG_FILE.to_vec();
+        // Corrupt the last byte of the gAMA chunk's CRC
+        bytes[61] ^= 0xff;
+
+        assert!(Png::try_from(bytes.as_ref()).is_err());
+
+        let png = Png::try_from_lenient(bytes.as_ref()).unwrap();
+        let invalid: Vec<String> = png
+            .chunks()
+            .iter()
+            .filter(|chunk| !chunk.is_crc_valid())
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(invalid, ["gAMA"]);
+    }
+
     #[test]
     fn test_list_chunks() {
         let png = testing_png();
@@ -256,6 +773,18 @@ mod tests {
         assert_eq!(chunks.len(), 3);
     }
 
+    #[test]
+    fn test_chunks_of_parsed_png() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let chunks = png.chunks();
+        assert_eq!(chunks.len(), 7);
+        assert_eq!(&chunks[5].chunk_type().to_string(), "RuSt");
+
+        let mut types = png.iter().map(|chunk| chunk.chunk_type().to_string());
+        assert_eq!(types.next().unwrap(), "IHDR");
+        assert_eq!(types.next_back().unwrap(), "IEND");
+    }
+
     #[test]
     fn test_chunk_by_type() {
         let png = testing_png();
@@ -282,6 +811,418 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_remove_all_chunks() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "first").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "second").unwrap());
+        let length = png.chunks().len();
+
+        let removed = png.remove_all_chunks("TeSt").unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(removed[1].data_as_string().unwrap(), "second");
+        assert_eq!(png.chunks().len(), length - 2);
+        assert!(png.remove_all_chunks("TeSt").is_err());
+    }
+
+    #[test]
+    fn test_remove_nth_chunk() {
+        let mut png = Png::default();
+        for message in ["zero", "one", "two"] {
+            png.insert_chunk_before_iend(chunk_from_strings("ruSt", message).unwrap());
+        }
+
+        let removed = png.remove_nth_chunk("ruSt", 1).unwrap();
+        assert_eq!(removed.data_as_string().unwrap(), "one");
+        let remaining: Vec<String> = png
+            .chunks_by_type("ruSt")
+            .map(|chunk| chunk.data_as_string().unwrap())
+            .collect();
+        assert_eq!(remaining, ["zero", "two"]);
+
+        let err = png.remove_nth_chunk("ruSt", 2).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no ruSt chunk at occurrence 2, the file has 2"
+        );
+    }
+
+    #[test]
+    fn test_remove_all_chunks_single_and_none() {
+        let mut png = testing_png();
+        assert!(matches!(
+            png.remove_all_chunks("NoNe"),
+            Err(PngError::ChunkNotFound())
+        ));
+        assert_eq!(png.chunks().len(), 3);
+
+        let removed = png.remove_all_chunks("FrSt").unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(png.chunks().len(), 2);
+        assert!(png.chunk_by_type("FrSt").is_none());
+    }
+
+    #[test]
+    fn test_wrap_data() {
+        let data = [0u8, 1, 2, 255, 0];
+        let png = Png::wrap_data(&data, ChunkType::from_str("ruSt").unwrap());
+
+        let parsed = Png::try_from(&png.as_bytes()[..]).unwrap();
+        assert!(parsed.validate().is_empty());
+        assert_eq!(parsed.chunks().len(), 4);
+        assert_eq!(parsed.chunk_by_type("ruSt").unwrap().data(), data);
+    }
+
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = Png::default();
+        png.insert_chunk(1, chunk_from_strings("RuSt", "first").unwrap())
+            .unwrap();
+        png.insert_chunk(3, chunk_from_strings("RuSt", "last").unwrap())
+            .unwrap();
+        let types: Vec<String> = png.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "RuSt", "IDAT", "RuSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_before_ihdr_is_error() {
+        let mut png = Png::default();
+        let chunk = chunk_from_strings("RuSt", "hidden").unwrap();
+        assert!(matches!(
+            png.insert_chunk(0, chunk),
+            Err(PngError::InvalidInsertIndex(0))
+        ));
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_insert_chunk_after_iend_is_error() {
+        let mut png = Png::default();
+        for index in [3, 4] {
+            let chunk = chunk_from_strings("RuSt", "hidden").unwrap();
+            assert!(png.insert_chunk(index, chunk).is_err());
+        }
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let path = std::env::temp_dir().join(format!("pngme-png-{}.png", std::process::id()));
+        let png = testing_png();
+        png.write_file(&path).unwrap();
+
+        let read = Png::from_file(&path).unwrap();
+        assert_eq!(read.as_bytes(), png.as_bytes());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(Png::from_file(&path), Err(PngError::Io(_))));
+    }
+
+    #[test]
+    fn test_insert_chunk_at() {
+        let mut png = Png::default();
+        for index in [0, 4] {
+            png.insert_chunk_at(index, chunk_from_strings("RuSt", "x").unwrap())
+                .unwrap();
+        }
+        let types: Vec<String> = png.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, ["RuSt", "IHDR", "IDAT", "IEND", "RuSt"]);
+
+        let chunk = chunk_from_strings("RuSt", "x").unwrap();
+        assert!(matches!(
+            png.insert_chunk_at(6, chunk),
+            Err(PngError::InsertIndexOutOfRange { index: 6, count: 5 })
+        ));
+    }
+
+    #[test]
+    fn test_insert_chunk_before_iend() {
+        let mut png = Png::default();
+        png.insert_chunk_before_iend(chunk_from_strings("RuSt", "hidden").unwrap());
+        let bytes = png.as_bytes();
+        assert_eq!(&bytes[bytes.len() - 8..bytes.len() - 4], b"IEND");
+        assert_eq!(png.chunks()[2].chunk_type().to_string(), "RuSt");
+
+        let mut png = testing_png();
+        png.insert_chunk_before_iend(chunk_from_strings("RuSt", "hidden").unwrap());
+        assert_eq!(png.chunks()[3].chunk_type().to_string(), "RuSt");
+    }
+
+    #[test]
+    fn test_insert_after_type() {
+        let mut png = Png::default();
+        png.insert_after_type("IHDR", chunk_from_strings("tEXt", "Title\0Dice").unwrap())
+            .unwrap();
+
+        assert_eq!(png.chunks().len(), 4);
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "tEXt");
+
+        let missing = chunk_from_strings("tEXt", "Title\0Dice").unwrap();
+        assert!(png.insert_after_type("PLTE", missing).is_err());
+    }
+
+    #[test]
+    fn test_insert_before_type_and_replace_chunk() {
+        let mut png = Png::default();
+        png.insert_before_type("IEND", chunk_from_strings("tEXt", "Title\0Dice").unwrap())
+            .unwrap();
+        assert_eq!(png.chunks()[2].chunk_type().to_string(), "tEXt");
+        assert_eq!(png.chunks()[3].chunk_type().to_string(), "IEND");
+
+        let replaced = png
+            .replace_chunk(2, chunk_from_strings("tEXt", "Title\0Die").unwrap())
+            .unwrap();
+        assert_eq!(replaced.data_as_string().unwrap(), "Title\0Dice");
+        assert_eq!(png.chunks()[2].data_as_string().unwrap(), "Title\0Die");
+        assert!(png.replace_chunk(4, replaced).is_none());
+    }
+
+    #[test]
+    fn test_replace_first_chunk() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let replacement = chunk_from_strings("tEXt", "Comment\0hey").unwrap();
+        let replaced = png.replace_first_chunk("RuSt", replacement).unwrap();
+
+        assert_eq!(replaced.data_as_string().unwrap(), "hey");
+        assert_eq!(png.chunks()[5].chunk_type().to_string(), "tEXt");
+        assert!(png
+            .replace_first_chunk("RuSt", Chunk::new(replaced.chunk_type().clone(), vec![]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let removed = png.retain(|chunk| chunk.chunk_type().is_critical());
+
+        let types = |chunks: &[Chunk]| -> Vec<String> {
+            chunks.iter().map(|c| c.chunk_type().to_string()).collect()
+        };
+        assert_eq!(types(&removed), ["sRGB", "gAMA", "pHYs"]);
+        assert_eq!(types(png.chunks()), ["IHDR", "IDAT", "RuSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_rechunk_idat() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let original = png.chunk_by_type("IDAT").unwrap().data().to_vec();
+        png.rechunk_idat(1000);
+
+        let idats: Vec<&Chunk> = png
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type().to_string() == "IDAT")
+            .collect();
+        assert_eq!(idats.len(), 5);
+        assert!(idats.iter().all(|c| c.length() <= 1000 && c.is_crc_valid()));
+
+        let rejoined: Vec<u8> = idats.iter().flat_map(|c| c.data()).copied().collect();
+        assert_eq!(rejoined, original);
+
+        // The IDAT chunks stay where the original one was
+        assert_eq!(&png.chunks()[4].chunk_type().to_string(), "IDAT");
+        assert_eq!(&png.chunks()[9].chunk_type().to_string(), "RuSt");
+    }
+
+    #[test]
+    fn test_rechunk_idat_small_is_unchanged() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.rechunk_idat(1 << 20);
+        assert_eq!(png.as_bytes(), PNG_FILE);
+    }
+
+    #[test]
+    fn test_merge_idat() {
+        let mut png = Png::default();
+        let original = png.chunk_by_type("IDAT").unwrap().data().to_vec();
+        png.rechunk_idat(4);
+        assert_eq!(png.chunks().len(), 5);
+
+        png.merge_idat().unwrap();
+        assert_eq!(png.as_bytes(), Png::default().as_bytes());
+        assert_eq!(png.chunk_by_type("IDAT").unwrap().data(), original);
+    }
+
+    #[test]
+    fn test_merge_idat_only_merges_runs() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IDAT", "a").unwrap(),
+            chunk_from_strings("tEXt", "b").unwrap(),
+            chunk_from_strings("IDAT", "c").unwrap(),
+            chunk_from_strings("IDAT", "d").unwrap(),
+        ]);
+        png.merge_idat().unwrap();
+
+        let data: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.data_as_string().unwrap())
+            .collect();
+        assert_eq!(data, ["a", "b", "cd"]);
+        assert!(png.chunks()[2].is_crc_valid());
+    }
+
+    #[test]
+    fn test_normalize_order() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "").unwrap(),
+            chunk_from_strings("tEXt", "a").unwrap(),
+            chunk_from_strings("IDAT", "b").unwrap(),
+            chunk_from_strings("gAMA", "c").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+            chunk_from_strings("PLTE", "d").unwrap(),
+            chunk_from_strings("IDAT", "e").unwrap(),
+            chunk_from_strings("tRNS", "f").unwrap(),
+        ]);
+        assert!(png.normalize_order());
+
+        let types: Vec<String> = png.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(
+            types,
+            ["IHDR", "tEXt", "gAMA", "PLTE", "tRNS", "IDAT", "IDAT", "IEND"]
+        );
+        assert_eq!(png.chunks()[5].data(), b"b");
+        assert!(validate::validate_ancillary_placement(&png).is_empty());
+        assert!(!png.normalize_order());
+    }
+
+    #[test]
+    fn test_normalize_order_keeps_unknown_chunks_in_place() {
+        let types = ["IHDR", "acTL", "fcTL", "IDAT", "fcTL", "fdAT", "IEND"];
+        let mut png = Png::from_chunks(
+            types
+                .iter()
+                .map(|chunk_type| chunk_from_strings(chunk_type, "").unwrap())
+                .collect(),
+        );
+        assert!(!png.normalize_order());
+
+        // A misplaced gAMA moves before the IDAT chunk, but not past acTL
+        png.insert_chunk(4, chunk_from_strings("gAMA", "").unwrap())
+            .unwrap();
+        assert!(png.normalize_order());
+        let types: Vec<String> = png.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(
+            types,
+            ["IHDR", "acTL", "fcTL", "gAMA", "IDAT", "fcTL", "fdAT", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_remove_duplicate_ancillary() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("tEXt", "a").unwrap(),
+            chunk_from_strings("IDAT", "b").unwrap(),
+            chunk_from_strings("IDAT", "b").unwrap(),
+            chunk_from_strings("tEXt", "a").unwrap(),
+            chunk_from_strings("tEXt", "c").unwrap(),
+            chunk_from_strings("ruSt", "a").unwrap(),
+        ]);
+        assert_eq!(png.remove_duplicate_ancillary(), 1);
+        assert_eq!(png.chunks().len(), 5);
+        assert_eq!(png.remove_duplicate_ancillary(), 0);
+    }
+
+    #[test]
+    fn test_nth_chunk_by_type() {
+        let mut png = Png::default();
+        png.rechunk_idat(4);
+        assert_eq!(
+            png.nth_chunk_by_type("IDAT", 1).unwrap().data(),
+            [0, 0, 0, 2]
+        );
+
+        let err = png.nth_chunk_by_type("IDAT", 3).unwrap_err();
+        assert!(matches!(
+            err,
+            PngError::OccurrenceOutOfRange { n: 3, count: 3, .. }
+        ));
+    }
+
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = Png::default();
+        let original = png.chunk_by_type("IDAT").unwrap().data().to_vec();
+        png.rechunk_idat(4);
+
+        let idats: Vec<&Chunk> = png.chunks_by_type("IDAT").collect();
+        assert_eq!(idats.len(), 3);
+        assert_eq!(
+            idats
+                .iter()
+                .flat_map(|c| c.data())
+                .copied()
+                .collect::<Vec<u8>>(),
+            original
+        );
+        assert_eq!(png.chunks_by_type("PLTE").count(), 0);
+        assert_eq!(png.chunks_by_type("bad").count(), 0);
+    }
+
+    #[test]
+    fn test_chunk_by_type_bytes() {
+        let odd_type = ChunkType::from_bytes_lenient([0xde, 0xad, 0xbe, 0xef]);
+        let mut png = Png::default();
+        png.append_chunk(Chunk::new(odd_type, b"odd".to_vec()));
+
+        let chunk = png.chunk_by_type_bytes([0xde, 0xad, 0xbe, 0xef]).unwrap();
+        assert_eq!(chunk.data(), b"odd");
+        assert!(png.chunk_by_type_bytes(*b"ruSt").is_none());
+    }
+
+    #[test]
+    fn test_critical_fingerprint() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let fingerprint = png.critical_fingerprint().unwrap();
+
+        let mut edited = Png::try_from(&PNG_FILE[..]).unwrap();
+        edited
+            .insert_after_type("IHDR", chunk_from_strings("tEXt", "Title\0Dice").unwrap())
+            .unwrap();
+        edited.rechunk_idat(1000);
+        assert_eq!(edited.critical_fingerprint().unwrap(), fingerprint);
+
+        let mut idat = edited.chunk_by_type("IDAT").unwrap().data().to_vec();
+        idat[0] ^= 0x01;
+        edited
+            .replace_first_chunk(
+                "IDAT",
+                Chunk::new(ChunkType::from_str("IDAT").unwrap(), idat),
+            )
+            .unwrap();
+        assert_ne!(edited.critical_fingerprint().unwrap(), fingerprint);
+    }
+
+    #[test]
+    fn test_chunk_type_set() {
+        let mut png = Png::default();
+        png.rechunk_idat(4);
+
+        let types: Vec<String> = png.chunk_type_set().into_iter().collect();
+        assert_eq!(types, ["IDAT", "IEND", "IHDR"]);
+    }
+
+    #[test]
+    fn test_try_from_streams() {
+        let mut second = Png::default();
+        second.rechunk_idat(4);
+        let mut bytes = Png::default().as_bytes();
+        bytes.extend(second.as_bytes());
+        bytes.extend(b"trailing");
+
+        let streams = Png::try_from_streams(&bytes, false).unwrap();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].as_bytes(), Png::default().as_bytes());
+        assert_eq!(streams[1].as_bytes(), second.as_bytes());
+    }
+
+    #[test]
+    fn test_try_from_streams_single() {
+        let streams = Png::try_from_streams(&PNG_FILE, false).unwrap();
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams[0].as_bytes(), PNG_FILE);
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -296,6 +1237,20 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_byte_range_of_chunk() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+
+        let range = png.byte_range_of_chunk(4).unwrap();
+        assert_eq!(range.start, 83);
+        assert_eq!(range.len(), 12 + png.chunks()[4].data().len());
+        assert_eq!(PNG_FILE[range], png.chunks()[4].as_bytes());
+
+        let last = png.byte_range_of_chunk(6).unwrap();
+        assert_eq!(last.end, PNG_FILE.len());
+        assert!(png.byte_range_of_chunk(7).is_none());
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -314,6 +1269,23 @@ mod tests {
         let _png_string = format!("{}", png);
     }
 
+    #[test]
+    fn test_default_is_valid_png() {
+        let bytes = Png::default().as_bytes();
+        let png = Png::try_from(bytes.as_ref()).unwrap();
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "IDAT", "IEND"]);
+
+        let ihdr = png.chunk_by_type("IHDR").unwrap().data();
+        assert_eq!(u32::from_be_bytes(ihdr[0..4].try_into().unwrap()), 1);
+        assert_eq!(u32::from_be_bytes(ihdr[4..8].try_into().unwrap()), 1);
+    }
+
     // This is the raw bytes for a shrunken version of the `dice.png` image on Wikipedia
     const PNG_FILE: [u8; 4803] = [
         137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 50, 0, 0, 0, 50, 8,
@@ -0,0 +1,33 @@
+use std::fs;
+use std::io::{self, Read, Write};
+
+/// Path that stands for stdin when reading and stdout when writing
+pub const STDIO_PATH: &str = "-";
+
+/// Whether a path stands for stdin or stdout
+pub fn is_stdio(path: &str) -> bool {
+    path == STDIO_PATH
+}
+
+/// Reads all of a file, or all of stdin for `-`
+pub fn read_input(path: &str) -> io::Result<Vec<u8>> {
+    if !is_stdio(path) {
+        return fs::read(path);
+    }
+
+    let mut bytes = Vec::new();
+    io::stdin().lock().read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Writes bytes to a file, or to stdout for `-`. Rust doesn't translate line endings on stdout,
+/// so binary data passes through unchanged on Windows too
+pub fn write_output(path: &str, bytes: &[u8]) -> io::Result<()> {
+    if !is_stdio(path) {
+        return fs::write(path, bytes);
+    }
+
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(bytes)?;
+    stdout.flush()
+}
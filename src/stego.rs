@@ -0,0 +1,296 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{IhdrData, IhdrError};
+use crate::png::Png;
+use crate::zlib::{self, ZlibError};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Bytes of the big-endian length stored in front of a message
+const LENGTH_BYTES: usize = 4;
+
+#[derive(Error, Debug)]
+pub enum StegoError {
+    #[error("no IHDR chunk")]
+    MissingIhdr(),
+
+    #[error("no IDAT chunk")]
+    MissingIdat(),
+
+    #[error("invalid IHDR: {0}")]
+    Ihdr(#[from] IhdrError),
+
+    #[error("cannot decompress the image data: {0}")]
+    Zlib(#[from] ZlibError),
+
+    #[error("only non-interlaced grayscale or truecolor images with 8-bit samples are supported")]
+    UnsupportedImage(),
+
+    #[error("image data is {got} bytes, expected {expected}")]
+    InvalidImageSize { got: usize, expected: usize },
+
+    #[error("unknown row filter type {0}")]
+    InvalidFilter(u8),
+
+    #[error("message is {length} bytes but the pixels can hold at most {capacity}")]
+    MessageTooLong { length: usize, capacity: usize },
+
+    #[error("no message is hidden in the pixels")]
+    NoMessage(),
+}
+
+/// Hides a message in the least significant bit of each pixel sample, after the message length.
+/// The image data is rewritten as a single IDAT chunk with unfiltered rows and no compression, so
+/// the file grows to about the size of the raw image
+pub fn embed(png: &mut Png, message: &[u8]) -> Result<(), StegoError> {
+    let (ihdr, mut samples) = read_samples(png)?;
+    let capacity = capacity_of(samples.len());
+    if message.len() > capacity {
+        return Err(StegoError::MessageTooLong {
+            length: message.len(),
+            capacity,
+        });
+    }
+
+    let length = (message.len() as u32).to_be_bytes();
+    let bits = length
+        .iter()
+        .chain(message)
+        .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1));
+    for (sample, bit) in samples.iter_mut().zip(bits) {
+        *sample = (*sample & !1) | bit;
+    }
+
+    // Filter type 0 leaves each row as it is
+    let stride = row_length(&ihdr);
+    let mut image_data = Vec::with_capacity(samples.len() + ihdr.height as usize);
+    for row in samples.chunks(stride) {
+        image_data.push(0);
+        image_data.extend(row);
+    }
+    let idat = Chunk::new(
+        ChunkType::from_str("IDAT").unwrap(),
+        zlib::compress_stored(&image_data),
+    );
+
+    let first_idat = png
+        .iter()
+        .position(|chunk| chunk.chunk_type().to_string() == "IDAT")
+        .ok_or(StegoError::MissingIdat())?;
+    png.replace_chunk(first_idat, idat);
+    let mut seen_idat = false;
+    png.retain(|chunk| {
+        let is_idat = chunk.chunk_type().to_string() == "IDAT";
+        let keep = !(is_idat && seen_idat);
+        seen_idat |= is_idat;
+        keep
+    });
+    Ok(())
+}
+
+/// Recovers a message hidden by `embed`
+pub fn extract(png: &Png) -> Result<Vec<u8>, StegoError> {
+    let (_, samples) = read_samples(png)?;
+    let mut bytes = samples.chunks_exact(8).map(|bits| {
+        bits.iter()
+            .fold(0, |byte, sample| (byte << 1) | (sample & 1))
+    });
+
+    let length: Vec<u8> = bytes.by_ref().take(LENGTH_BYTES).collect();
+    let length = match length.try_into() {
+        Ok(length) => u32::from_be_bytes(length) as usize,
+        Err(_) => return Err(StegoError::NoMessage()),
+    };
+    if length > capacity_of(samples.len()) {
+        return Err(StegoError::NoMessage());
+    }
+    Ok(bytes.take(length).collect())
+}
+
+/// Message bytes that fit in this many samples, one bit per sample, after the length
+fn capacity_of(samples: usize) -> usize {
+    (samples / 8).saturating_sub(LENGTH_BYTES)
+}
+
+/// Bytes in one row of pixels, not counting the filter type byte
+fn row_length(ihdr: &IhdrData) -> usize {
+    let channels = match ihdr.color_type {
+        0 => 1,
+        2 => 3,
+        4 => 2,
+        _ => 4,
+    };
+    ihdr.width as usize * channels
+}
+
+/// Decompresses and unfilters the image data, returning every sample row after row without the
+/// filter type bytes
+fn read_samples(png: &Png) -> Result<(IhdrData, Vec<u8>), StegoError> {
+    let ihdr = png.chunk_by_type("IHDR").ok_or(StegoError::MissingIhdr())?;
+    let ihdr = IhdrData::try_from(ihdr)?;
+    let supported = ihdr.bit_depth == 8
+        && matches!(ihdr.color_type, 0 | 2 | 4 | 6)
+        && ihdr.interlace_method == 0;
+    if !supported {
+        return Err(StegoError::UnsupportedImage());
+    }
+
+    let compressed: Vec<u8> = png
+        .chunks_by_type("IDAT")
+        .flat_map(|chunk| chunk.data())
+        .copied()
+        .collect();
+    if compressed.is_empty() {
+        return Err(StegoError::MissingIdat());
+    }
+    let stride = row_length(&ihdr);
+    let expected = (stride + 1) * ihdr.height as usize;
+    let image_data = zlib::decompress(&compressed, expected)?;
+    if image_data.len() != expected {
+        return Err(StegoError::InvalidImageSize {
+            got: image_data.len(),
+            expected,
+        });
+    }
+
+    // Filters work on whole pixels, which is one byte per channel at 8 bits per sample
+    let pixel_length = stride / ihdr.width.max(1) as usize;
+    let mut samples: Vec<u8> = Vec::with_capacity(stride * ihdr.height as usize);
+    for (y, row) in image_data.chunks(stride + 1).enumerate() {
+        let (filter, row) = (row[0], &row[1..]);
+        for (x, &byte) in row.iter().enumerate() {
+            let left = if x >= pixel_length {
+                samples[y * stride + x - pixel_length]
+            } else {
+                0
+            };
+            let up = if y > 0 {
+                samples[(y - 1) * stride + x]
+            } else {
+                0
+            };
+            let up_left = if y > 0 && x >= pixel_length {
+                samples[(y - 1) * stride + x - pixel_length]
+            } else {
+                0
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(StegoError::InvalidFilter(filter)),
+            };
+            samples.push(byte.wrapping_add(predicted));
+        }
+    }
+    Ok((ihdr, samples))
+}
+
+/// The neighbouring byte closest to `left + up - up_left`, as used by filter type 4
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance = |byte: u8| (estimate - byte as i16).abs();
+    if distance(left) <= distance(up) && distance(left) <= distance(up_left) {
+        left
+    } else if distance(up) <= distance(up_left) {
+        up
+    } else {
+        up_left
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    /// A PNG with the given IHDR fields whose compressed image data is split over two IDAT chunks
+    fn testing_png(width: u8, height: u8, color_type: u8, image_data: &[u8]) -> Png {
+        let ihdr = vec![0, 0, 0, width, 0, 0, 0, height, 8, color_type, 0, 0, 0];
+        let stream = zlib::compress_stored(image_data);
+        let (first, second) = stream.split_at(stream.len() / 2);
+        Png::from_chunks(vec![
+            chunk("IHDR", ihdr),
+            chunk("IDAT", first.to_vec()),
+            chunk("IDAT", second.to_vec()),
+            chunk("IEND", vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_read_samples_unfilters_rows() {
+        // A 2x4 RGB image with one row for each of the Sub, Up, Average and Paeth filters
+        let image_data = [
+            [1, 1, 2, 3, 1, 1, 1],
+            [2, 1, 1, 1, 1, 1, 1],
+            [3, 0, 0, 0, 0, 0, 0],
+            [4, 0, 0, 0, 0, 0, 0],
+        ];
+        let png = testing_png(2, 4, 2, image_data.as_flattened());
+
+        let (_, samples) = read_samples(&png).unwrap();
+        assert_eq!(
+            samples,
+            [
+                [1, 2, 3, 2, 3, 4],
+                [2, 3, 4, 3, 4, 5],
+                [1, 1, 2, 2, 2, 3],
+                [1, 1, 2, 2, 2, 3]
+            ]
+            .as_flattened()
+        );
+    }
+
+    #[test]
+    fn test_embed_and_extract() {
+        let mut image_data = vec![];
+        for y in 0..4u8 {
+            image_data.push(0);
+            image_data.extend((0..64).map(|x| x * 3 + y));
+        }
+        let mut png = testing_png(16, 4, 6, &image_data);
+        let (_, original) = read_samples(&png).unwrap();
+        assert_eq!(capacity_of(original.len()), 28);
+
+        embed(&mut png, b"hidden").unwrap();
+        assert_eq!(extract(&png).unwrap(), b"hidden");
+        let types: Vec<String> = png.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "IDAT", "IEND"]);
+
+        // Only the lowest bit of each sample may change
+        let (_, samples) = read_samples(&png).unwrap();
+        assert!(samples.iter().zip(&original).all(|(a, b)| a >> 1 == b >> 1));
+    }
+
+    #[test]
+    fn test_embed_errors() {
+        let mut png = testing_png(16, 4, 6, &[0; 65 * 4]);
+        assert!(matches!(
+            embed(&mut png, &[0; 29]),
+            Err(StegoError::MessageTooLong {
+                length: 29,
+                capacity: 28
+            })
+        ));
+        assert!(matches!(extract(&png), Ok(message) if message.is_empty()));
+
+        let mut png = testing_png(16, 4, 3, &[0; 17 * 4]);
+        assert!(matches!(
+            embed(&mut png, b"hi"),
+            Err(StegoError::UnsupportedImage())
+        ));
+    }
+
+    #[test]
+    fn test_paeth() {
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(10, 10, 30), 10);
+        assert_eq!(paeth(50, 60, 55), 55);
+    }
+}
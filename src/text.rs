@@ -0,0 +1,280 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::zlib::{self, ZlibError};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Chunk types that hold a keyword and a text value
+pub const TEXT_CHUNK_TYPES: [&str; 3] = ["tEXt", "zTXt", "iTXt"];
+
+#[derive(Error, Debug)]
+pub enum TextError {
+    #[error("{0} is not a text chunk")]
+    NotTextChunk(String),
+
+    #[error("missing NUL after the keyword")]
+    MissingKeyword(),
+
+    #[error("cannot decompress text: {0}")]
+    Decompress(#[from] ZlibError),
+
+    #[error("iTXt text is not valid UTF-8")]
+    InvalidUtf8(),
+
+    #[error("invalid keyword {0:?}, keywords are 1 to 79 printable Latin-1 characters")]
+    InvalidKeyword(String),
+
+    #[error("tEXt can only hold Latin-1 text")]
+    NotLatin1(),
+
+    #[error("tEXt text cannot contain a NUL byte")]
+    NulInText(),
+
+    #[error("no IEND chunk to add text before")]
+    MissingIend(),
+}
+
+/// Keyword and value of a tEXt, zTXt or iTXt chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEntry {
+    pub keyword: String,
+    pub text: String,
+}
+
+/// Decodes a text chunk. tEXt and zTXt hold Latin-1 text, iTXt holds UTF-8
+pub fn parse_text_chunk(chunk: &Chunk) -> Result<TextEntry, TextError> {
+    let chunk_type = chunk.chunk_type().to_string();
+    if !TEXT_CHUNK_TYPES.contains(&chunk_type.as_str()) {
+        return Err(TextError::NotTextChunk(chunk_type));
+    }
+
+    let (keyword, rest) = chunk
+        .data_split_first(0)
+        .ok_or(TextError::MissingKeyword())?;
+    let keyword = latin1_to_string(keyword);
+
+    let text = match chunk_type.as_str() {
+        "tEXt" => latin1_to_string(rest),
+        "zTXt" => latin1_to_string(&zlib::decompress_chunk(chunk)?),
+        _ => String::from_utf8(zlib::decompress_chunk(chunk)?)
+            .map_err(|_| TextError::InvalidUtf8())?,
+    };
+
+    Ok(TextEntry { keyword, text })
+}
+
+/// Decodes every text chunk of a PNG in file order, leaving out those that can't be decoded
+pub fn text_entries(png: &Png) -> impl Iterator<Item = TextEntry> + '_ {
+    png.chunks()
+        .iter()
+        .filter_map(|chunk| parse_text_chunk(chunk).ok())
+}
+
+/// Name of the tool that wrote the PNG, from its Software text or else a comment in the
+/// `Created with <tool>` form that some editors write
+pub fn producer(png: &Png) -> Option<String> {
+    let entries: Vec<TextEntry> = text_entries(png).collect();
+    let software = entries.iter().find(|entry| entry.keyword == "Software");
+    if let Some(entry) = software {
+        return Some(entry.text.trim().to_string());
+    }
+
+    entries
+        .iter()
+        .filter(|entry| entry.keyword == "Comment")
+        .find_map(|entry| entry.text.trim().strip_prefix("Created with "))
+        .map(str::to_string)
+}
+
+/// Builds a tEXt chunk, checking the keyword against the PNG specification's rules
+pub fn text_chunk(keyword: &str, text: &str) -> Result<Chunk, TextError> {
+    let is_printable = |c: char| matches!(c, ' '..='~' | '\u{a1}'..='\u{ff}');
+    let valid_keyword = (1..=79).contains(&keyword.chars().count())
+        && keyword.chars().all(is_printable)
+        && !keyword.starts_with(' ')
+        && !keyword.ends_with(' ')
+        && !keyword.contains("  ");
+    if !valid_keyword {
+        return Err(TextError::InvalidKeyword(keyword.to_string()));
+    }
+
+    if text.contains('\0') {
+        return Err(TextError::NulInText());
+    }
+
+    let mut data = string_to_latin1(keyword).ok_or(TextError::NotLatin1())?;
+    data.push(0);
+    data.extend(string_to_latin1(text).ok_or(TextError::NotLatin1())?);
+    Ok(Chunk::new(ChunkType::from_str("tEXt").unwrap(), data))
+}
+
+/// Sets a tEXt entry: the first tEXt chunk with the keyword is replaced in place, or else a new one
+/// is added right before IEND
+pub fn set_text(png: &mut Png, keyword: &str, text: &str) -> Result<(), TextError> {
+    let chunk = text_chunk(keyword, text)?;
+    let existing = png.iter().position(|chunk| {
+        chunk.chunk_type().to_string() == "tEXt"
+            && parse_text_chunk(chunk).is_ok_and(|entry| entry.keyword == keyword)
+    });
+    match existing {
+        Some(index) => {
+            png.replace_chunk(index, chunk);
+        }
+        None => png
+            .insert_before_type("IEND", chunk)
+            .map_err(|_| TextError::MissingIend())?,
+    }
+    Ok(())
+}
+
+/// Removes every text chunk with the keyword, returning how many were removed
+pub fn remove_text(png: &mut Png, keyword: &str) -> usize {
+    png.retain(|chunk| !parse_text_chunk(chunk).is_ok_and(|entry| entry.keyword == keyword))
+        .len()
+}
+
+/// Encodes text as Latin-1, if every character has a Latin-1 code point
+fn string_to_latin1(text: &str) -> Option<Vec<u8>> {
+    text.chars().map(|c| u8::try_from(c).ok()).collect()
+}
+
+/// Latin-1 maps each byte to the Unicode code point of the same value
+pub fn latin1_to_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// zlib compression of "Made with pngme"
+    const COMPRESSED_TEXT: [u8; 23] = [
+        120, 218, 243, 77, 76, 73, 85, 40, 207, 44, 201, 80, 40, 200, 75, 207, 77, 5, 0, 42, 251,
+        5, 139,
+    ];
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_set_text() {
+        let mut png = Png::default();
+        png.insert_after_type("IHDR", chunk("tEXt", b"Title\0Old"))
+            .unwrap();
+
+        set_text(&mut png, "Title", "My Image").unwrap();
+        set_text(&mut png, "Author", "Me").unwrap();
+        let entries: Vec<(String, String)> = text_entries(&png)
+            .map(|entry| (entry.keyword, entry.text))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("Title".to_string(), "My Image".to_string()),
+                ("Author".to_string(), "Me".to_string())
+            ]
+        );
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "tEXt");
+        assert_eq!(png.chunks().len(), 5);
+
+        assert!(set_text(&mut png, " Title", "x").is_err());
+    }
+
+    #[test]
+    fn test_remove_text() {
+        let mut png = Png::default();
+        png.insert_after_type("IHDR", chunk("tEXt", b"Title\0Dice"))
+            .unwrap();
+        png.insert_after_type("IHDR", chunk("iTXt", b"Title\0\0\0\0\0Dice"))
+            .unwrap();
+        png.insert_after_type("IHDR", chunk("tEXt", b"Author\0Me"))
+            .unwrap();
+
+        assert_eq!(remove_text(&mut png, "Title"), 2);
+        assert_eq!(remove_text(&mut png, "Title"), 0);
+        let keywords: Vec<String> = text_entries(&png).map(|entry| entry.keyword).collect();
+        assert_eq!(keywords, ["Author"]);
+    }
+
+    #[test]
+    fn test_parse_text() {
+        let entry = parse_text_chunk(&chunk("tEXt", b"Author\0Caf\xe9")).unwrap();
+        assert_eq!(entry.keyword, "Author");
+        assert_eq!(entry.text, "Café");
+    }
+
+    #[test]
+    fn test_parse_compressed_text() {
+        let mut data = b"Comment\0\0".to_vec();
+        data.extend(COMPRESSED_TEXT);
+        let entry = parse_text_chunk(&chunk("zTXt", &data)).unwrap();
+        assert_eq!(entry.keyword, "Comment");
+        assert_eq!(entry.text, "Made with pngme");
+    }
+
+    #[test]
+    fn test_parse_international_text() {
+        let entry = parse_text_chunk(&chunk("iTXt", "Title\0\0\0fr\0Titre\0Été".as_bytes()));
+        assert_eq!(entry.unwrap().text, "Été");
+    }
+
+    #[test]
+    fn test_text_chunk_round_trip() {
+        let chunk = text_chunk("Comment", "Café").unwrap();
+        assert_eq!(chunk.data(), b"Comment\0Caf\xe9");
+
+        let entry = parse_text_chunk(&chunk).unwrap();
+        assert_eq!(entry.keyword, "Comment");
+        assert_eq!(entry.text, "Café");
+    }
+
+    #[test]
+    fn test_text_chunk_errors() {
+        for keyword in ["", " Comment", "Two  spaces", "Tab\there", &"k".repeat(80)] {
+            assert!(matches!(
+                text_chunk(keyword, "text"),
+                Err(TextError::InvalidKeyword(_))
+            ));
+        }
+        assert!(matches!(
+            text_chunk("Comment", "snowman ☃"),
+            Err(TextError::NotLatin1())
+        ));
+        assert!(matches!(
+            text_chunk("Comment", "before\0after"),
+            Err(TextError::NulInText())
+        ));
+    }
+
+    #[test]
+    fn test_producer() {
+        let mut png = Png::default();
+        assert_eq!(producer(&png), None);
+
+        png.insert_after_type("IHDR", text_chunk("Comment", "Created with GIMP").unwrap())
+            .unwrap();
+        assert_eq!(producer(&png).unwrap(), "GIMP");
+
+        let software = text_chunk("Software", "Adobe ImageReady").unwrap();
+        png.insert_after_type("IHDR", software).unwrap();
+        assert_eq!(producer(&png).unwrap(), "Adobe ImageReady");
+    }
+
+    #[test]
+    fn test_parse_text_errors() {
+        assert!(matches!(
+            parse_text_chunk(&chunk("tEXt", b"no keyword")),
+            Err(TextError::MissingKeyword())
+        ));
+        assert!(matches!(
+            parse_text_chunk(&chunk("zTXt", b"Comment\0\0not zlib")),
+            Err(TextError::Decompress(_))
+        ));
+        assert!(matches!(
+            parse_text_chunk(&chunk("IDAT", b"")),
+            Err(TextError::NotTextChunk(_))
+        ));
+    }
+}
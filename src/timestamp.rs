@@ -0,0 +1,161 @@
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TimestampError {
+    #[error("tIME data is {0} bytes, expected 7")]
+    InvalidLength(usize),
+
+    #[error("{0} is not a valid date and time")]
+    OutOfRange(String),
+
+    #[error("expected a date like 2024-01-31, got {0:?}")]
+    InvalidFormat(String),
+}
+
+/// A UTC time as stored in a tIME chunk. Fields are ordered from most to least significant, so
+/// the derived ordering is chronological
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Timestamp {
+    fn validate(self) -> Result<Timestamp, TimestampError> {
+        // A second of 60 allows for leap seconds
+        let valid = (1..=12).contains(&self.month)
+            && (1..=days_in_month(self.year, self.month)).contains(&self.day)
+            && self.hour <= 23
+            && self.minute <= 59
+            && self.second <= 60;
+        if valid {
+            Ok(self)
+        } else {
+            Err(TimestampError::OutOfRange(self.to_string()))
+        }
+    }
+}
+
+/// Number of days in a month of the Gregorian calendar, or 0 for a month that doesn't exist
+fn days_in_month(year: u16, month: u8) -> u8 {
+    let leap_year =
+        year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap_year => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+impl TryFrom<&[u8]> for Timestamp {
+    type Error = TimestampError;
+
+    /// Parse the data of a tIME chunk
+    fn try_from(data: &[u8]) -> Result<Timestamp, TimestampError> {
+        if data.len() != 7 {
+            return Err(TimestampError::InvalidLength(data.len()));
+        }
+
+        Timestamp {
+            year: u16::from_be_bytes([data[0], data[1]]),
+            month: data[2],
+            day: data[3],
+            hour: data[4],
+            minute: data[5],
+            second: data[6],
+        }
+        .validate()
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = TimestampError;
+
+    /// Parse a `YYYY-MM-DD` date, as the start of that day
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || TimestampError::InvalidFormat(s.to_string());
+        let mut parts = s.split('-');
+        let (Some(year), Some(month), Some(day), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+
+        Timestamp {
+            year: year.parse().map_err(|_| invalid())?,
+            month: month.parse().map_err(|_| invalid())?,
+            day: day.parse().map_err(|_| invalid())?,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        }
+        .validate()
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp_from_time_data() {
+        let timestamp = Timestamp::try_from(&[7, 232, 2, 29, 13, 45, 0][..]).unwrap();
+        assert_eq!(timestamp.to_string(), "2024-02-29 13:45:00");
+
+        assert_eq!(
+            Timestamp::try_from(&[7, 232, 13, 1, 0, 0, 0][..]),
+            Err(TimestampError::OutOfRange(
+                "2024-13-01 00:00:00".to_string()
+            ))
+        );
+        assert_eq!(
+            Timestamp::try_from(&[7, 232][..]),
+            Err(TimestampError::InvalidLength(2))
+        );
+    }
+
+    #[test]
+    fn test_timestamp_from_str() {
+        let date: Timestamp = "2024-01-05".parse().unwrap();
+        assert_eq!(date.to_string(), "2024-01-05 00:00:00");
+
+        assert!("2024-01".parse::<Timestamp>().is_err());
+        assert!("2024-01-05-01".parse::<Timestamp>().is_err());
+        assert!("2024-00-05".parse::<Timestamp>().is_err());
+    }
+
+    #[test]
+    fn test_timestamp_day_depends_on_month() {
+        assert!("2024-04-30".parse::<Timestamp>().is_ok());
+        assert!("2024-04-31".parse::<Timestamp>().is_err());
+        assert!("2024-02-29".parse::<Timestamp>().is_ok());
+        assert!("2023-02-29".parse::<Timestamp>().is_err());
+        assert!("2000-02-29".parse::<Timestamp>().is_ok());
+        assert!("1900-02-29".parse::<Timestamp>().is_err());
+    }
+
+    #[test]
+    fn test_timestamp_ordering() {
+        let earlier = Timestamp::try_from(&[7, 231, 12, 31, 23, 59, 59][..]).unwrap();
+        let later: Timestamp = "2024-01-01".parse().unwrap();
+        assert!(earlier < later);
+    }
+}
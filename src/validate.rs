@@ -0,0 +1,512 @@
+use crate::png::Png;
+use std::fmt;
+
+/// How serious a validation issue is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The file is valid but something about it is worth knowing
+    Warning,
+    /// The file breaks the PNG specification
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found while checking a PNG file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    InvalidSignature,
+    InvalidCrc {
+        index: usize,
+        chunk_type: String,
+        got: u32,
+        expected: u32,
+    },
+    IhdrNotFirst,
+    IendNotLast,
+    DuplicateChunk {
+        chunk_type: String,
+        count: usize,
+    },
+    MissingIdat,
+    NonContiguousIdat {
+        index: usize,
+    },
+    PlteAfterIdat {
+        index: usize,
+    },
+    MissingPlte,
+    PrivateChunk {
+        index: usize,
+        chunk_type: String,
+    },
+    TrailingData {
+        length: usize,
+    },
+    MisplacedAncillary {
+        index: usize,
+        chunk_type: String,
+        placement: Placement,
+    },
+    InvalidChunkType {
+        index: usize,
+        chunk_type: String,
+    },
+}
+
+/// Where the PNG specification allows an ancillary chunk to appear
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    BeforePalette,
+    AfterPalette,
+    BeforeImageData,
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Placement::BeforePalette => write!(f, "before PLTE and IDAT"),
+            Placement::AfterPalette => write!(f, "after PLTE and before IDAT"),
+            Placement::BeforeImageData => write!(f, "before IDAT"),
+        }
+    }
+}
+
+/// Ancillary chunks with a restricted position. Those not listed, such as tIME and the text
+/// chunks, may appear anywhere
+const ANCILLARY_PLACEMENT: [(&str, Placement); 10] = [
+    ("cHRM", Placement::BeforePalette),
+    ("gAMA", Placement::BeforePalette),
+    ("iCCP", Placement::BeforePalette),
+    ("sBIT", Placement::BeforePalette),
+    ("sRGB", Placement::BeforePalette),
+    ("bKGD", Placement::AfterPalette),
+    ("hIST", Placement::AfterPalette),
+    ("tRNS", Placement::AfterPalette),
+    ("pHYs", Placement::BeforeImageData),
+    ("sPLT", Placement::BeforeImageData),
+];
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationIssue::InvalidSignature => {
+                write!(f, "file does not start with the PNG signature")
+            }
+            ValidationIssue::InvalidCrc {
+                index,
+                chunk_type,
+                got,
+                expected,
+            } => write!(
+                f,
+                "chunk {} ({}) has CRC {:08x}, expected {:08x}",
+                index, chunk_type, got, expected
+            ),
+            ValidationIssue::IhdrNotFirst => write!(f, "first chunk is not IHDR"),
+            ValidationIssue::IendNotLast => write!(f, "last chunk is not IEND"),
+            ValidationIssue::DuplicateChunk { chunk_type, count } => {
+                write!(
+                    f,
+                    "{} appears {} times but is only allowed once",
+                    chunk_type, count
+                )
+            }
+            ValidationIssue::MissingIdat => write!(f, "no IDAT chunk"),
+            ValidationIssue::NonContiguousIdat { index } => {
+                write!(
+                    f,
+                    "IDAT chunk {} does not follow the previous IDAT chunk",
+                    index
+                )
+            }
+            ValidationIssue::PlteAfterIdat { index } => {
+                write!(f, "PLTE chunk {} comes after the image data", index)
+            }
+            ValidationIssue::MissingPlte => write!(f, "palette image has no PLTE chunk"),
+            ValidationIssue::PrivateChunk { index, chunk_type } => write!(
+                f,
+                "chunk {} ({}) is a private chunk and may hold hidden data",
+                index, chunk_type
+            ),
+            ValidationIssue::TrailingData { length } => {
+                write!(f, "{} bytes of trailing data after IEND", length)
+            }
+            ValidationIssue::MisplacedAncillary {
+                index,
+                chunk_type,
+                placement,
+            } => write!(
+                f,
+                "chunk {} ({}) must come {}",
+                index, chunk_type, placement
+            ),
+            ValidationIssue::InvalidChunkType { index, chunk_type } => write!(
+                f,
+                "chunk {} ({}) has a type that isn't four ASCII letters",
+                index, chunk_type
+            ),
+        }
+    }
+}
+
+impl ValidationIssue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            ValidationIssue::PrivateChunk { .. } | ValidationIssue::TrailingData { .. } => {
+                Severity::Warning
+            }
+            _ => Severity::Error,
+        }
+    }
+
+    /// Stable identifier for the kind of issue. Codes are never reused or renumbered
+    pub fn code(&self) -> &'static str {
+        match self {
+            ValidationIssue::IendNotLast => "PNG001",
+            ValidationIssue::InvalidCrc { .. } => "PNG002",
+            ValidationIssue::NonContiguousIdat { .. } => "PNG003",
+            ValidationIssue::IhdrNotFirst => "PNG004",
+            ValidationIssue::DuplicateChunk { .. } => "PNG005",
+            ValidationIssue::MissingIdat => "PNG006",
+            ValidationIssue::PlteAfterIdat { .. } => "PNG007",
+            ValidationIssue::PrivateChunk { .. } => "PNG008",
+            ValidationIssue::InvalidSignature => "PNG009",
+            ValidationIssue::MissingPlte => "PNG010",
+            ValidationIssue::TrailingData { .. } => "PNG011",
+            ValidationIssue::MisplacedAncillary { .. } => "PNG012",
+            ValidationIssue::InvalidChunkType { .. } => "PNG013",
+        }
+    }
+
+    /// Index of the chunk the issue is about, if it concerns a single chunk
+    pub fn chunk_index(&self) -> Option<usize> {
+        match self {
+            ValidationIssue::InvalidCrc { index, .. }
+            | ValidationIssue::NonContiguousIdat { index }
+            | ValidationIssue::PlteAfterIdat { index }
+            | ValidationIssue::PrivateChunk { index, .. }
+            | ValidationIssue::MisplacedAncillary { index, .. }
+            | ValidationIssue::InvalidChunkType { index, .. } => Some(*index),
+            _ => None,
+        }
+    }
+}
+
+/// Checks that the bytes start with the standard PNG signature
+pub fn validate_signature(bytes: &[u8]) -> Vec<ValidationIssue> {
+    if bytes.starts_with(&Png::STANDARD_HEADER) {
+        vec![]
+    } else {
+        vec![ValidationIssue::InvalidSignature]
+    }
+}
+
+/// Checks every chunk's CRC against its type and data
+pub fn validate_crcs(png: &Png) -> Vec<ValidationIssue> {
+    png.chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| !chunk.is_crc_valid())
+        .map(|(index, chunk)| ValidationIssue::InvalidCrc {
+            index,
+            chunk_type: chunk.chunk_type().to_string(),
+            got: chunk.crc(),
+            expected: chunk.calculated_crc(),
+        })
+        .collect()
+}
+
+/// Checks that every chunk type is made of ASCII letters. Parsing accepts any type bytes, as
+/// files from other tools may use them
+pub fn validate_chunk_types(png: &Png) -> Vec<ValidationIssue> {
+    png.chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| {
+            !chunk
+                .chunk_type()
+                .bytes()
+                .iter()
+                .all(u8::is_ascii_alphabetic)
+        })
+        .map(|(index, chunk)| ValidationIssue::InvalidChunkType {
+            index,
+            chunk_type: chunk.chunk_type().to_string(),
+        })
+        .collect()
+}
+
+/// Checks the ordering rules for critical chunks: IHDR first, IEND last, PLTE before the image
+/// data and present for palette images, and the IDAT chunks present and consecutive
+pub fn validate_structure(png: &Png) -> Vec<ValidationIssue> {
+    let types: Vec<String> = png
+        .chunks()
+        .iter()
+        .map(|chunk| chunk.chunk_type().to_string())
+        .collect();
+    let mut issues = vec![];
+
+    if types.first().map(String::as_str) != Some("IHDR") {
+        issues.push(ValidationIssue::IhdrNotFirst);
+    }
+    if types.last().map(String::as_str) != Some("IEND") {
+        issues.push(ValidationIssue::IendNotLast);
+    }
+
+    for unique in ["IHDR", "PLTE", "IEND"] {
+        let count = types.iter().filter(|t| *t == unique).count();
+        if count > 1 {
+            issues.push(ValidationIssue::DuplicateChunk {
+                chunk_type: unique.to_string(),
+                count,
+            });
+        }
+    }
+
+    // Colour type 3 in the IHDR data means the pixels are indices into the palette
+    let is_palette_image = png
+        .chunk_by_type("IHDR")
+        .is_some_and(|ihdr| ihdr.data().get(9) == Some(&3));
+    if is_palette_image && !types.iter().any(|t| t == "PLTE") {
+        issues.push(ValidationIssue::MissingPlte);
+    }
+
+    let idat_indices: Vec<usize> = (0..types.len()).filter(|&i| types[i] == "IDAT").collect();
+    match idat_indices.first() {
+        None => issues.push(ValidationIssue::MissingIdat),
+        Some(&first_idat) => {
+            for pair in idat_indices.windows(2) {
+                if pair[1] != pair[0] + 1 {
+                    issues.push(ValidationIssue::NonContiguousIdat { index: pair[1] });
+                }
+            }
+
+            for (index, _) in types.iter().enumerate().filter(|(_, t)| *t == "PLTE") {
+                if index > first_idat {
+                    issues.push(ValidationIssue::PlteAfterIdat { index });
+                }
+            }
+        }
+    }
+
+    issues
+}
+
+/// Where the PNG specification allows an ancillary chunk type to appear, if it is restricted
+pub fn ancillary_placement(chunk_type: &str) -> Option<Placement> {
+    ANCILLARY_PLACEMENT
+        .iter()
+        .find(|(t, _)| *t == chunk_type)
+        .map(|(_, placement)| *placement)
+}
+
+/// Checks that each ancillary chunk with a restricted position is where the PNG specification
+/// allows, relative to the first PLTE and IDAT chunks
+pub fn validate_ancillary_placement(png: &Png) -> Vec<ValidationIssue> {
+    let first = |chunk_type: &str| {
+        png.iter()
+            .position(|chunk| chunk.chunk_type().to_string() == chunk_type)
+    };
+    let (plte, idat) = (first("PLTE"), first("IDAT"));
+    // A missing PLTE or IDAT places no limit
+    let before = |index: usize, limit: Option<usize>| limit.is_none_or(|limit| index < limit);
+    let after = |index: usize, limit: Option<usize>| limit.is_none_or(|limit| index > limit);
+
+    png.iter()
+        .enumerate()
+        .filter_map(|(index, chunk)| {
+            let chunk_type = chunk.chunk_type().to_string();
+            let placement = ancillary_placement(&chunk_type)?;
+            let allowed = match placement {
+                Placement::BeforePalette => before(index, plte) && before(index, idat),
+                Placement::AfterPalette => after(index, plte) && before(index, idat),
+                Placement::BeforeImageData => before(index, idat),
+            };
+            (!allowed).then_some(ValidationIssue::MisplacedAncillary {
+                index,
+                chunk_type,
+                placement,
+            })
+        })
+        .collect()
+}
+
+/// Lists the chunks that aren't part of the public specification
+pub fn find_private_chunks(png: &Png) -> Vec<ValidationIssue> {
+    png.chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| !chunk.chunk_type().is_public())
+        .map(|(index, chunk)| ValidationIssue::PrivateChunk {
+            index,
+            chunk_type: chunk.chunk_type().to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_default_png_is_valid() {
+        let png = Png::default();
+        assert!(validate_signature(&png.as_bytes()).is_empty());
+        assert!(validate_crcs(&png).is_empty());
+        assert!(validate_structure(&png).is_empty());
+        assert!(find_private_chunks(&png).is_empty());
+    }
+
+    #[test]
+    fn test_ancillary_placement() {
+        let mut png = Png::default();
+        png.insert_after_type("IHDR", chunk("gAMA", &[0, 0, 177, 143]))
+            .unwrap();
+        png.insert_after_type("IHDR", chunk("tIME", &[7, 232, 1, 1, 0, 0, 0]))
+            .unwrap();
+        assert!(validate_ancillary_placement(&png).is_empty());
+
+        png.insert_after_type("IDAT", chunk("gAMA", &[0, 0, 177, 143]))
+            .unwrap();
+        png.insert_after_type("IDAT", chunk("tEXt", b"Title\0Dice"))
+            .unwrap();
+        let issues = validate_ancillary_placement(&png);
+        assert_eq!(
+            issues,
+            [ValidationIssue::MisplacedAncillary {
+                index: 5,
+                chunk_type: "gAMA".to_string(),
+                placement: Placement::BeforePalette,
+            }]
+        );
+        assert_eq!(
+            issues[0].to_string(),
+            "chunk 5 (gAMA) must come before PLTE and IDAT"
+        );
+    }
+
+    #[test]
+    fn test_palette_ancillary_placement() {
+        let palette_chunks = |types: &[&str]| {
+            let mut png = Png::default();
+            for chunk_type in types.iter().rev() {
+                png.insert_after_type("IHDR", chunk(chunk_type, &[0, 0, 0]))
+                    .unwrap();
+            }
+            validate_ancillary_placement(&png).len()
+        };
+        assert_eq!(palette_chunks(&["sRGB", "PLTE", "tRNS"]), 0);
+        assert_eq!(palette_chunks(&["tRNS", "PLTE", "sRGB"]), 2);
+        // Without a palette tRNS only has to come before IDAT
+        assert_eq!(palette_chunks(&["tRNS"]), 0);
+    }
+
+    #[test]
+    fn test_invalid_signature() {
+        assert_eq!(
+            validate_signature(&[0xff, 0xd8, 0xff, 0xe0, 0, 0, 0, 0]),
+            [ValidationIssue::InvalidSignature]
+        );
+    }
+
+    #[test]
+    fn test_invalid_crc() {
+        let mut bytes = Png::default().as_bytes();
+        // Corrupt the CRC of the IHDR chunk
+        bytes[32] ^= 0xff;
+        let png = Png::try_from_lenient(&bytes).unwrap();
+
+        let issues = validate_crcs(&png);
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(
+            &issues[0],
+            ValidationIssue::InvalidCrc { index: 0, chunk_type, .. } if chunk_type == "IHDR"
+        ));
+    }
+
+    #[test]
+    fn test_invalid_chunk_type() {
+        let mut bytes = Png::default().as_bytes();
+        // Change the type of the IDAT chunk to `I1AT`, with a matching CRC
+        bytes[38] = b'1';
+        let mut png = Png::try_from_lenient(&bytes).unwrap();
+        png.recalculate_crcs();
+
+        let issues = validate_chunk_types(&png);
+        assert_eq!(
+            issues,
+            [ValidationIssue::InvalidChunkType {
+                index: 1,
+                chunk_type: "I1AT".to_string(),
+            }]
+        );
+        assert_eq!(issues[0].code(), "PNG013");
+        assert!(validate_crcs(&png).is_empty());
+    }
+
+    #[test]
+    fn test_structure_issues() {
+        let png = Png::from_chunks(vec![
+            chunk("tEXt", b"Title\0x"),
+            chunk("IHDR", &[0; 13]),
+            chunk("IDAT", &[]),
+            chunk("tIME", &[0; 7]),
+            chunk("IDAT", &[]),
+            chunk("PLTE", &[0; 3]),
+        ]);
+
+        assert_eq!(
+            validate_structure(&png),
+            [
+                ValidationIssue::IhdrNotFirst,
+                ValidationIssue::IendNotLast,
+                ValidationIssue::NonContiguousIdat { index: 4 },
+                ValidationIssue::PlteAfterIdat { index: 5 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_idat() {
+        let png = Png::from_chunks(vec![chunk("IHDR", &[0; 13]), chunk("IEND", &[])]);
+        assert_eq!(validate_structure(&png), [ValidationIssue::MissingIdat]);
+    }
+
+    #[test]
+    fn test_missing_plte() {
+        let palette_ihdr = [0, 0, 0, 1, 0, 0, 0, 1, 8, 3, 0, 0, 0];
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &palette_ihdr),
+            chunk("IDAT", &[]),
+            chunk("IEND", &[]),
+        ]);
+        assert_eq!(validate_structure(&png), [ValidationIssue::MissingPlte]);
+    }
+
+    #[test]
+    fn test_private_chunks() {
+        let mut png = Png::default();
+        png.append_chunk(chunk("ruSt", b"hidden"));
+
+        let issues = find_private_chunks(&png);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity(), Severity::Warning);
+        assert_eq!(issues[0].code(), "PNG008");
+        assert_eq!(issues[0].chunk_index(), Some(3));
+    }
+}
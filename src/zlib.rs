@@ -0,0 +1,510 @@
+use crate::chunk::Chunk;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ZlibError {
+    #[error("invalid zlib header")]
+    InvalidHeader(),
+
+    #[error("compressed data ends unexpectedly")]
+    UnexpectedEnd(),
+
+    #[error("invalid compressed data: {reason}")]
+    InvalidData { reason: String },
+
+    #[error("decompressed data is larger than {0} bytes")]
+    TooLarge(usize),
+
+    #[error("adler-32 checksum of the decompressed data does not match")]
+    ChecksumMismatch(),
+
+    #[error("{0} chunks do not hold compressed data")]
+    UnsupportedChunkType(String),
+
+    #[error("unexpected {chunk_type} layout: {reason}")]
+    InvalidLayout { chunk_type: String, reason: String },
+}
+
+fn invalid(reason: &str) -> ZlibError {
+    ZlibError::InvalidData {
+        reason: reason.to_string(),
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which the code lengths of the code length alphabet are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Reads a byte slice least significant bit first, as deflate stores it
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            position: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn bits(&mut self, n: u32) -> Result<u32, ZlibError> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or(ZlibError::UnexpectedEnd())?;
+            self.position += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << n) - 1);
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Skips to the next byte boundary
+    fn align(&mut self) {
+        let partial = self.count % 8;
+        self.buffer >>= partial;
+        self.count -= partial;
+    }
+
+    /// Position of the next unread byte. Only meaningful after `align`
+    fn byte_position(&self) -> usize {
+        self.position - (self.count / 8) as usize
+    }
+}
+
+/// Canonical Huffman code, stored as the number of codes of each length and the symbols sorted
+/// by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, ZlibError> {
+        // `first` is the first code of the current length and `index` the position of its symbol
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("no symbol for code"))
+    }
+}
+
+/// Largest output `decompress_chunk` allows, well above any real text or ICC profile
+pub const MAX_CHUNK_OUTPUT: usize = 64 * 1024 * 1024;
+
+/// Decompresses a zlib stream, checking its header and adler-32 checksum. Stops with an error as
+/// soon as the output would grow past `max_output` bytes, so a small stream can't expand without
+/// bound
+pub fn decompress(data: &[u8], max_output: usize) -> Result<Vec<u8>, ZlibError> {
+    let (cmf, flg) = match data {
+        [cmf, flg, ..] => (*cmf, *flg),
+        _ => return Err(ZlibError::UnexpectedEnd()),
+    };
+    // Deflate compression, a check value that makes the header a multiple of 31 and no preset
+    // dictionary
+    let check = (cmf as u16) << 8 | flg as u16;
+    if cmf & 0x0f != 8 || !check.is_multiple_of(31) || flg & 0x20 != 0 {
+        return Err(ZlibError::InvalidHeader());
+    }
+
+    let mut reader = BitReader::new(&data[2..]);
+    let output = inflate(&mut reader, max_output)?;
+
+    reader.align();
+    let position = 2 + reader.byte_position();
+    let checksum = data
+        .get(position..position + 4)
+        .ok_or(ZlibError::UnexpectedEnd())?;
+    if u32::from_be_bytes(checksum.try_into().unwrap()) != adler32(&output) {
+        return Err(ZlibError::ChecksumMismatch());
+    }
+
+    Ok(output)
+}
+
+/// Wraps data in a zlib stream of stored deflate blocks. Nothing is compressed, so the stream is a
+/// little larger than the data, but any zlib decoder can read it
+pub fn compress_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = u16::MAX as usize;
+    // Deflate with a 32K window, no preset dictionary and the lowest compression level
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        stream.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let length = block.len() as u16;
+        // BFINAL in the lowest bit and BTYPE 00 for stored, padded to a byte boundary
+        stream.push(is_final as u8);
+        stream.extend(length.to_le_bytes());
+        stream.extend((!length).to_le_bytes());
+        stream.extend(block);
+    }
+    stream.extend(adler32(data).to_be_bytes());
+    stream
+}
+
+/// Decompresses the zlib stream held by a zTXt, iTXt or iCCP chunk, skipping the type-specific
+/// fields that come before it. Uncompressed iTXt text is returned as is. The output is limited to
+/// `MAX_CHUNK_OUTPUT` bytes
+pub fn decompress_chunk(chunk: &Chunk) -> Result<Vec<u8>, ZlibError> {
+    let chunk_type = chunk.chunk_type().to_string();
+    let layout_error = |reason: &str| ZlibError::InvalidLayout {
+        chunk_type: chunk_type.clone(),
+        reason: reason.to_string(),
+    };
+
+    // All three start with a NUL-terminated keyword or profile name
+    let (_, rest) = chunk
+        .data_split_first(0)
+        .ok_or_else(|| layout_error("missing NUL after the keyword"))?;
+
+    match chunk_type.as_str() {
+        "zTXt" | "iCCP" => match rest {
+            [0, stream @ ..] => decompress(stream, MAX_CHUNK_OUTPUT),
+            [_, ..] => Err(layout_error("unknown compression method")),
+            [] => Err(layout_error("missing compression method")),
+        },
+        "iTXt" => {
+            let (flag, method, rest) = match rest {
+                [flag, method, rest @ ..] => (*flag, *method, rest),
+                _ => return Err(layout_error("missing compression flag")),
+            };
+            // Skip the NUL-terminated language tag and translated keyword
+            let mut text = rest;
+            for _ in 0..2 {
+                let end = text
+                    .iter()
+                    .position(|&b| b == 0)
+                    .ok_or_else(|| layout_error("missing NUL after the language fields"))?;
+                text = &text[end + 1..];
+            }
+            match (flag, method) {
+                (0, _) => Ok(text.to_vec()),
+                (1, 0) => decompress(text, MAX_CHUNK_OUTPUT),
+                (1, _) => Err(layout_error("unknown compression method")),
+                _ => Err(layout_error("invalid compression flag")),
+            }
+        }
+        _ => Err(ZlibError::UnsupportedChunkType(chunk_type)),
+    }
+}
+
+fn inflate(reader: &mut BitReader, max_output: usize) -> Result<Vec<u8>, ZlibError> {
+    let mut output = vec![];
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => inflate_stored(reader, &mut output, max_output)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                inflate_block(reader, &mut output, max_output, &literals, &distances)?
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(reader)?;
+                inflate_block(reader, &mut output, max_output, &literals, &distances)?
+            }
+            _ => return Err(invalid("reserved block type")),
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+fn inflate_stored(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_output: usize,
+) -> Result<(), ZlibError> {
+    reader.align();
+    let length = reader.bits(16)?;
+    if length != !reader.bits(16)? & 0xffff {
+        return Err(invalid("stored block length does not match its complement"));
+    }
+    check_output_size(output.len() + length as usize, max_output)?;
+    for _ in 0..length {
+        output.push(reader.bits(8)? as u8);
+    }
+    Ok(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), ZlibError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match code_length_code.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| invalid("repeat with no previous length"))?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(length, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err(invalid("code lengths overrun"));
+    }
+    if lengths[256] == 0 {
+        return Err(invalid("no end of block code"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_output: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), ZlibError> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        if symbol < 256 {
+            check_output_size(output.len() + 1, max_output)?;
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let index = symbol - 257;
+        if index >= LENGTH_BASE.len() {
+            return Err(invalid("invalid length symbol"));
+        }
+        let length =
+            LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+        let index = distances.decode(reader)? as usize;
+        if index >= DISTANCE_BASE.len() {
+            return Err(invalid("invalid distance symbol"));
+        }
+        let distance =
+            DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+        if distance > output.len() {
+            return Err(invalid("distance reaches before the start of the data"));
+        }
+        check_output_size(output.len() + length, max_output)?;
+
+        // Copy byte by byte, the source and destination may overlap
+        let start = output.len() - distance;
+        for i in 0..length {
+            output.push(output[start + i]);
+        }
+    }
+}
+
+fn check_output_size(length: usize, max_output: usize) -> Result<(), ZlibError> {
+    if length > max_output {
+        return Err(ZlibError::TooLarge(max_output));
+    }
+    Ok(())
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % MODULUS;
+        b = (b + a) % MODULUS;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    /// zlib compression of `ICC_PROFILE`
+    const COMPRESSED_ICC_PROFILE: [u8; 58] = [
+        120, 218, 75, 75, 204, 78, 85, 240, 116, 118, 86, 40, 40, 202, 79, 203, 204, 73, 181, 82,
+        96, 96, 100, 98, 102, 97, 101, 99, 231, 224, 228, 226, 230, 225, 229, 227, 23, 16, 20, 18,
+        22, 17, 21, 19, 151, 144, 148, 146, 150, 145, 149, 147, 7, 0, 13, 16, 7, 226,
+    ];
+
+    fn icc_profile() -> Vec<u8> {
+        let mut profile = b"fake ICC profile: ".to_vec();
+        profile.extend(0..32);
+        profile
+    }
+
+    #[test]
+    fn test_decompress_stored() {
+        let data = [120, 1, 1, 2, 0, 253, 255, 104, 105, 1, 59, 0, 210];
+        assert_eq!(decompress(&data, usize::MAX).unwrap(), b"hi");
+    }
+
+    #[test]
+    fn test_compress_stored() {
+        assert_eq!(
+            compress_stored(b"hi"),
+            [120, 1, 1, 2, 0, 253, 255, 104, 105, 1, 59, 0, 210]
+        );
+        assert_eq!(decompress(&compress_stored(b""), usize::MAX).unwrap(), b"");
+
+        let data: Vec<u8> = (0..=255).cycle().take(150_000).collect();
+        assert_eq!(
+            decompress(&compress_stored(&data), usize::MAX).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn test_decompress_fixed() {
+        let data = [
+            120, 218, 203, 72, 205, 201, 201, 87, 200, 64, 144, 0, 58, 46, 6, 125,
+        ];
+        assert_eq!(decompress(&data, usize::MAX).unwrap(), b"hello hello hello");
+    }
+
+    #[test]
+    fn test_decompress_dynamic() {
+        let data = [
+            120, 218, 29, 136, 193, 17, 0, 48, 12, 64, 102, 37, 246, 159, 161, 105, 30, 238, 32, 3,
+            242, 89, 9, 38, 219, 214, 251, 169, 225, 244, 0, 57, 140, 15, 81,
+        ];
+        assert_eq!(
+            decompress(&data, usize::MAX).unwrap(),
+            b"bacaabaaabacaadaacdbdbaabbcaabadbbbdabcd"
+        );
+    }
+
+    #[test]
+    fn test_decompress_rejects_bad_data() {
+        let mut data = COMPRESSED_ICC_PROFILE;
+        data[57] ^= 0xff;
+        assert_eq!(
+            decompress(&data, usize::MAX),
+            Err(ZlibError::ChecksumMismatch())
+        );
+        assert_eq!(
+            decompress(&COMPRESSED_ICC_PROFILE[..30], usize::MAX),
+            Err(ZlibError::UnexpectedEnd())
+        );
+        assert_eq!(
+            decompress(&[0, 0], usize::MAX),
+            Err(ZlibError::InvalidHeader())
+        );
+        // The fixed code stream decompresses to 17 bytes
+        let hello = [
+            120, 218, 203, 72, 205, 201, 201, 87, 200, 64, 144, 0, 58, 46, 6, 125,
+        ];
+        assert_eq!(decompress(&hello, 16), Err(ZlibError::TooLarge(16)));
+        assert_eq!(decompress(&hello, 17).unwrap(), b"hello hello hello");
+        assert_eq!(
+            decompress(&compress_stored(&[0; 100]), 99),
+            Err(ZlibError::TooLarge(99))
+        );
+    }
+
+    #[test]
+    fn test_decompress_iccp_chunk() {
+        let mut data = b"Profile\0\0".to_vec();
+        data.extend(COMPRESSED_ICC_PROFILE);
+        let chunk = Chunk::new(ChunkType::from_str("iCCP").unwrap(), data);
+
+        assert_eq!(decompress_chunk(&chunk).unwrap(), icc_profile());
+    }
+
+    #[test]
+    fn test_decompress_chunk_layout_errors() {
+        let chunk = Chunk::new(ChunkType::from_str("iCCP").unwrap(), b"no NUL".to_vec());
+        assert!(matches!(
+            decompress_chunk(&chunk),
+            Err(ZlibError::InvalidLayout { .. })
+        ));
+
+        let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"a\0b".to_vec());
+        assert!(matches!(
+            decompress_chunk(&chunk),
+            Err(ZlibError::UnsupportedChunkType(_))
+        ));
+    }
+}
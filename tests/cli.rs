@@ -0,0 +1,1262 @@
+use std::fs;
+use std::io::Write;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// A small image with a `RuSt` chunk holding the message "hey"
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/dice.png");
+
+fn pngme(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pngme"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn pngme_with_stdin(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pngme"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The command may exit before reading all of stdin, e.g. on an argument error
+    let _ = child.stdin.take().unwrap().write_all(stdin);
+    child.wait_with_output().unwrap()
+}
+
+/// A file or directory in the temp directory unique to the calling test, removed when dropped so
+/// nothing is left behind even if the test fails
+struct TempPath(PathBuf);
+
+impl TempPath {
+    fn new(name: &str) -> TempPath {
+        TempPath(std::env::temp_dir().join(format!("pngme-{}-{}", std::process::id(), name)))
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = if self.0.is_dir() {
+            fs::remove_dir_all(&self.0)
+        } else {
+            fs::remove_file(&self.0)
+        };
+    }
+}
+
+/// Writes the fixture, altered by `edit`, to a file unique to the calling test
+fn fixture_copy(name: &str, edit: impl FnOnce(&mut Vec<u8>)) -> TempPath {
+    let mut bytes = fs::read(FIXTURE).unwrap();
+    edit(&mut bytes);
+    let path = TempPath::new(&format!("{}.png", name));
+    fs::write(&path, bytes).unwrap();
+    path
+}
+
+#[test]
+fn test_print_directory_is_friendly_error() {
+    let dir = std::env::temp_dir();
+    let output = pngme(&["print", dir.to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("is a directory, expected a file"));
+}
+
+#[test]
+fn test_decode_length_only() {
+    let output = pngme(&["decode", FIXTURE, "RuSt", "--length-only"]);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+}
+
+#[test]
+fn test_decode_leaves_file_unchanged() {
+    let path = fixture_copy("decode-unchanged", |_| {});
+    let before = fs::read(&path).unwrap();
+
+    let output = pngme(&["decode", path.to_str().unwrap(), "RuSt"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: hey\n"
+    );
+    assert_eq!(fs::read(&path).unwrap(), before);
+
+    let output = pngme(&["decode", path.to_str().unwrap(), "NoNe"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("chunk does not exist"));
+    assert_eq!(fs::read(&path).unwrap(), before);
+}
+
+#[test]
+fn test_remove_all() {
+    let path = fixture_copy("remove-all", |_| {});
+    let file = path.to_str().unwrap();
+    assert!(pngme(&["encode", file, "RuSt", "again"]).status.success());
+
+    let output = pngme(&["remove", file, "RuSt", "--all"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Removed 2 RuSt chunk(s)\n"
+    );
+
+    let before = fs::read(&path).unwrap();
+    let output = pngme(&["remove", file, "RuSt", "--all"]);
+    assert!(!output.status.success());
+    assert_eq!(fs::read(&path).unwrap(), before);
+}
+
+#[test]
+fn test_embed_and_reveal() {
+    let path = fixture_copy("embed", |_| {});
+    let file = path.to_str().unwrap();
+    assert!(pngme(&["embed", file, "in the pixels"]).status.success());
+
+    let output = pngme(&["reveal", file]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: in the pixels\n"
+    );
+    // The chunks are unchanged apart from the image data
+    let output = pngme(&["decode", file, "RuSt"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: hey\n"
+    );
+    assert!(pngme(&["verify", file]).status.success());
+
+    let long_message = "x".repeat(2000);
+    assert!(!pngme(&["embed", file, &long_message]).status.success());
+}
+
+#[test]
+fn test_decode_binary_message() {
+    let path = fixture_copy("decode-binary", |_| {});
+    let input = TempPath::new("binary.bin");
+    let saved = TempPath::new("saved.bin");
+    fs::write(&input, [b'o', b'k', 0xff, 0xfe, 0x80]).unwrap();
+    let file = path.to_str().unwrap();
+    let args = [
+        "encode",
+        file,
+        "BiNa",
+        "--input-file",
+        input.to_str().unwrap(),
+    ];
+    assert!(pngme(&args).status.success());
+
+    let output = pngme(&["decode", file, "BiNa"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: ok\u{fffd}\u{fffd}\u{fffd}\n"
+    );
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("not valid UTF-8"));
+
+    let output = pngme(&["decode", file, "BiNa", "--hex"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message (hex): 6f6bfffe80\n"
+    );
+    let output = pngme(&["decode", file, "RuSt", "--hex"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: hey\n"
+    );
+
+    let output = pngme(&["decode", file, "BiNa", "--output", saved.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(fs::read(&saved).unwrap(), fs::read(&input).unwrap());
+
+    // 0xc3 starts a two byte sequence but 0x28 is not a continuation byte
+    fs::write(&input, [b'a', 0xc3, 0x28, 0xe2, 0x82, b'b']).unwrap();
+    let args = [
+        "encode",
+        file,
+        "CoNt",
+        "--input-file",
+        input.to_str().unwrap(),
+    ];
+    assert!(pngme(&args).status.success());
+    let output = pngme(&["decode", file, "CoNt"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: a\u{fffd}(\u{fffd}b\n"
+    );
+    let output = pngme(&["decode", file, "CoNt", "--hex"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message (hex): 61c328e28262\n"
+    );
+}
+
+#[test]
+fn test_decode_every_chunk_of_type() {
+    let path = fixture_copy("decode-all", |_| {});
+    let file = path.to_str().unwrap();
+    assert!(pngme(&["encode", file, "RuSt", "there"]).status.success());
+
+    let output = pngme(&["decode", file, "RuSt"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: hey\nHidden message: there\n"
+    );
+
+    let output = pngme(&["decode", file, "RuSt", "--first"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: hey\n"
+    );
+}
+
+#[test]
+fn test_decode_max_messages() {
+    let path = fixture_copy("max-messages", |_| {});
+    let file = path.to_str().unwrap();
+    for message in ["two", "three", "four", "five"] {
+        assert!(pngme(&["encode", file, "RuSt", message]).status.success());
+    }
+
+    let output = pngme(&["decode", file, "RuSt", "--max-messages", "2"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: hey\nHidden message: two\n... (3 more)\n"
+    );
+
+    let output = pngme(&["decode", file, "RuSt", "--max-messages", "5"]);
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("more"));
+}
+
+#[test]
+fn test_canonicalize() {
+    // Move the gAMA chunk from after sRGB to just before IEND
+    let path = fixture_copy("canonicalize", |bytes| {
+        let gama: Vec<u8> = bytes.drain(46..62).collect();
+        bytes.splice(4775..4775, gama);
+    });
+    let pixels = TempPath::new("pixels.bin");
+    let file = path.to_str().unwrap();
+    let pixels_arg = pixels.to_str().unwrap();
+    assert!(pngme(&["rechunk", file, "--max-idat", "1000"])
+        .status
+        .success());
+    // APNG chunks, which only mean something where they are
+    for args in [
+        ["emPt", ""],
+        ["ruSt", "hi"],
+        ["ruSt", "hi"],
+        ["fcTL", "f"],
+        ["acTL", "a"],
+    ] {
+        let args = ["encode", file, args[0], args[1], "--index", "1"];
+        assert!(pngme(&args).status.success());
+    }
+    assert!(pngme(&["meta", "set", file, "Title=Dice"]).status.success());
+    assert!(pngme(&["decode", file, "IDAT", "--output", pixels_arg])
+        .status
+        .success());
+    let image_data = fs::read(&pixels).unwrap();
+
+    let output = pngme(&["canonicalize", file]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Fixed 0 CRC(s)\nReordered chunks\nDropped 1 empty chunk(s)\n\
+         Dropped 1 duplicate chunk(s)\nMerged 5 IDAT chunk(s) into 1\n"
+    );
+    let output = pngme(&["canonicalize", file]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Fixed 0 CRC(s)\nChunk order already canonical\nDropped 0 empty chunk(s)\n\
+         Dropped 0 duplicate chunk(s)\nIDAT chunks already merged\n"
+    );
+    let output = pngme(&["list", file]);
+    let types: Vec<String> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1).map(str::to_string))
+        .collect();
+    assert_eq!(
+        types,
+        ["IHDR", "acTL", "fcTL", "ruSt", "sRGB", "gAMA", "pHYs", "IDAT", "RuSt", "tEXt", "IEND"]
+    );
+    assert!(pngme(&["verify", file, "--strict"]).status.success());
+
+    assert!(pngme(&["decode", file, "IDAT", "--output", pixels_arg])
+        .status
+        .success());
+    assert_eq!(fs::read(&pixels).unwrap(), image_data);
+}
+
+#[test]
+fn test_strip() {
+    let path = fixture_copy("strip", |_| {});
+    let stripped = TempPath::new("stripped.png");
+    let file = path.to_str().unwrap();
+    assert!(pngme(&["encode", file, "ruSt", "hidden"]).status.success());
+
+    let output = pngme(&["strip", file, stripped.to_str().unwrap(), "--keep", "gAMA"]);
+    assert!(output.status.success());
+    // sRGB, pHYs and ruSt with 1, 9 and 6 bytes of data and 12 bytes of framing each
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Removed 3 chunk(s), 52 bytes\n"
+    );
+    let output = pngme(&["list", stripped.to_str().unwrap()]);
+    let types: Vec<String> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1).map(str::to_string))
+        .collect();
+    assert_eq!(types, ["IHDR", "gAMA", "IDAT", "RuSt", "IEND"]);
+
+    // Stripping a file with nothing to remove leaves it untouched
+    let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+    fs::File::options()
+        .write(true)
+        .open(&stripped)
+        .unwrap()
+        .set_modified(old)
+        .unwrap();
+    let output = pngme(&["strip", stripped.to_str().unwrap(), "--keep", "gAMA"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Removed 0 chunk(s), 0 bytes\n"
+    );
+    assert_eq!(fs::metadata(&stripped).unwrap().modified().unwrap(), old);
+}
+
+#[test]
+fn test_insert_at_index() {
+    let path = fixture_copy("insert", |_| {});
+    let data = TempPath::new("insert.bin");
+    fs::write(&data, b"\x00crafted").unwrap();
+    let (file, data_arg) = (path.to_str().unwrap(), data.to_str().unwrap());
+
+    let args = [
+        "insert",
+        file,
+        "ruSt",
+        "--data-file",
+        data_arg,
+        "--index",
+        "1",
+    ];
+    assert!(pngme(&args).status.success());
+    let output = pngme(&["list", file]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.lines().nth(1).unwrap().contains("ruSt"));
+
+    let args = [
+        "insert",
+        file,
+        "ruSt",
+        "--data-file",
+        data_arg,
+        "--index",
+        "9",
+    ];
+    let output = pngme(&args);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("the file has 8 chunks"));
+}
+
+#[test]
+fn test_remove_nth() {
+    let path = fixture_copy("remove-nth", |_| {});
+    let file = path.to_str().unwrap();
+    for message in ["zero", "one", "two"] {
+        assert!(pngme(&["encode", file, "ruSt", message]).status.success());
+    }
+
+    assert!(pngme(&["remove", file, "ruSt", "--nth", "1"])
+        .status
+        .success());
+    let output = pngme(&["decode", file, "ruSt"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: zero\nHidden message: two\n"
+    );
+
+    let output = pngme(&["remove", file, "ruSt", "--nth", "5"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("the file has 2"));
+}
+
+#[test]
+fn test_decode_type_bytes() {
+    // Give the RuSt chunk a type that isn't letters, leaving its CRC stale
+    let path = fixture_copy("type-bytes", |bytes| {
+        bytes[4780..4784].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef])
+    });
+    let output = pngme(&[
+        "decode",
+        path.to_str().unwrap(),
+        "--type-bytes",
+        "DEADBEEF",
+        "--fix-crc",
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: hey\n"
+    );
+
+    // With a matching CRC the file parses without --fix-crc
+    let path = fixture_copy("type-bytes-valid-crc", |bytes| {
+        bytes[4780..4784].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        let crc = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC).checksum(&bytes[4780..4787]);
+        bytes[4787..4791].copy_from_slice(&crc.to_be_bytes());
+    });
+    let file = path.to_str().unwrap();
+    let output = pngme(&["decode", file, "--type-bytes", "DEADBEEF"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: hey\n"
+    );
+}
+
+#[test]
+fn test_decode_fix_crc_flags_bad_chunk() {
+    // Corrupt the CRC of the gAMA chunk
+    let path = fixture_copy("fix-crc", |bytes| bytes[61] ^= 0xff);
+    let path = path.to_str().unwrap();
+
+    assert!(!pngme(&["decode", path, "RuSt"]).status.success());
+
+    let output = pngme(&["decode", path, "RuSt", "--fix-crc"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: hey\n"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("chunk 2 (gAMA)"));
+}
+
+#[test]
+fn test_audit_status() {
+    // The fixture holds its message in a private chunk
+    let output = pngme(&["audit", FIXTURE]);
+    assert_eq!(output.status.code(), Some(2));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("warning: chunk 5 (RuSt) is a private chunk"));
+    assert!(stdout.ends_with("status: warn\n"));
+
+    let path = fixture_copy("audit", |bytes| bytes[61] ^= 0xff);
+    let output = pngme(&["audit", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(3));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("error: chunk 2 (gAMA) has CRC"));
+    assert!(stdout.ends_with("status: fail\n"));
+
+    // A file that can't be read isn't mistaken for one with warnings
+    let output = pngme(&["audit", "no-such-file.png"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    // Nor is a directory
+    let output = pngme(&["audit", std::env::temp_dir().to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(4));
+}
+
+#[test]
+fn test_verify_reports_chunk_type_not_letters() {
+    // Rename RuSt to R1St and correct its CRC, so only the type is wrong
+    let path = fixture_copy("verify-type", |bytes| bytes[4781] = b'1');
+    assert!(pngme(&["fix", path.to_str().unwrap()]).status.success());
+
+    let output = pngme(&["verify", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "error: chunk 5 (R1St) has a type that isn't four ASCII letters\n"
+    );
+
+    let output = pngme(&["lint", path.to_str().unwrap()]);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with("PNG013 4776 "));
+}
+
+#[test]
+fn test_verify_reports_every_issue() {
+    let output = pngme(&["verify", FIXTURE]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "ok\n");
+
+    // Corrupt the gAMA CRC and append data after IEND
+    let path = fixture_copy("verify", |bytes| {
+        bytes[61] ^= 0xff;
+        bytes.extend(b"junk");
+    });
+    let output = pngme(&["verify", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("error: chunk 2 (gAMA) has CRC"));
+    assert_eq!(lines[1], "warning: 4 bytes of trailing data after IEND");
+}
+
+#[test]
+fn test_meta_set() {
+    let path = fixture_copy("meta-set", |_| {});
+    let file = path.to_str().unwrap();
+
+    let output = pngme(&["meta", "set", file, "Title=My Image", "Author=Me"]);
+    assert!(output.status.success());
+    let output = pngme(&["meta", "set", file, "Title=Dice"]);
+    assert!(output.status.success());
+
+    let output = pngme(&["text", file]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Title: Dice\nAuthor: Me\n"
+    );
+    assert!(!pngme(&["meta", "set", file, "Title"]).status.success());
+}
+
+#[test]
+fn test_meta_get_and_unset() {
+    let path = fixture_copy("meta-get", |_| {});
+    let file = path.to_str().unwrap();
+    assert!(pngme(&["meta", "set", file, "Title=Dice"]).status.success());
+
+    let output = pngme(&["meta", "get", file, "Title"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "Dice\n");
+    assert!(!pngme(&["meta", "get", file, "Author"]).status.success());
+
+    assert!(pngme(&["meta", "unset", file, "Title"]).status.success());
+    assert!(!pngme(&["meta", "get", file, "Title"]).status.success());
+    assert_eq!(fs::read(&path).unwrap(), fs::read(FIXTURE).unwrap());
+
+    assert!(pngme(&["meta", "unset", file, "Title"]).status.success());
+
+    // The output file is written even if there was nothing to remove
+    let copy = TempPath::new("meta-copy.png");
+    let args = [
+        "meta",
+        "unset",
+        file,
+        "Title",
+        "--output-file",
+        copy.to_str().unwrap(),
+    ];
+    assert!(pngme(&args).status.success());
+    assert_eq!(fs::read(&copy).unwrap(), fs::read(FIXTURE).unwrap());
+}
+
+#[test]
+fn test_fix_repairs_crc() {
+    let path = fixture_copy("fix", |bytes| bytes[61] ^= 0xff);
+    let file = path.to_str().unwrap();
+    assert!(!pngme(&["decode", file, "RuSt"]).status.success());
+
+    let output = pngme(&["fix", file]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Fixed 1 CRC(s)\n"
+    );
+    assert_eq!(fs::read(&path).unwrap(), fs::read(FIXTURE).unwrap());
+}
+
+#[test]
+fn test_verify_strict() {
+    let path = fixture_copy("verify-strict", |_| {});
+    let file = path.to_str().unwrap();
+    assert!(pngme(&["verify", file, "--strict"]).status.success());
+
+    // A second gAMA after the image data
+    let gama = ["encode", file, "gAMA", "abcd", "--after", "IDAT"];
+    assert!(pngme(&gama).status.success());
+    assert!(pngme(&["verify", file]).status.success());
+
+    let output = pngme(&["verify", file, "--strict"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "error: chunk 5 (gAMA) must come before PLTE and IDAT\n"
+    );
+}
+
+#[test]
+fn test_stat() {
+    let output = pngme(&["stat", FIXTURE]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "50x50 RGBA-8, 7 chunks, 3 ancillary, 4.8 KB, valid\n"
+    );
+
+    // Drop the IEND chunk
+    let path = fixture_copy("stat", |bytes| bytes.truncate(4791));
+    let output = pngme(&["stat", path.to_str().unwrap()]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "invalid: last chunk is not IEND\n"
+    );
+}
+
+#[test]
+fn test_lint_codes() {
+    // Corrupt the CRC of the gAMA chunk and drop the IEND chunk
+    let path = fixture_copy("lint", |bytes| {
+        bytes[61] ^= 0xff;
+        bytes.truncate(4791);
+    });
+    let output = pngme(&["lint", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let findings: Vec<(&str, &str)> = stdout
+        .lines()
+        .map(|line| {
+            let mut fields = line.split(' ');
+            (fields.next().unwrap(), fields.next().unwrap())
+        })
+        .collect();
+    assert_eq!(
+        findings,
+        [("PNG001", "-"), ("PNG002", "46"), ("PNG008", "4776")]
+    );
+}
+
+#[test]
+fn test_print_all_streams() {
+    let path = fixture_copy("all-streams", |bytes| {
+        bytes.extend(fs::read(FIXTURE).unwrap())
+    });
+    let output = pngme(&["print", path.to_str().unwrap(), "--all-streams"]);
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let (first, second) = stdout.split_once("stream 1:\n").unwrap();
+    assert!(first.starts_with("stream 0:\n"));
+    assert_eq!(first.lines().count(), 8);
+    assert_eq!(second.lines().count(), 7);
+    assert!(first.contains("RuSt") && second.contains("RuSt"));
+}
+
+#[test]
+fn test_encode_message0_keeps_newlines() {
+    let path = fixture_copy("message0", |_| ());
+    let path = path.to_str().unwrap();
+
+    let output = pngme_with_stdin(
+        &["encode", path, "ruSt", "--message0"],
+        b"first line\nsecond line\n\0not part of the message",
+    );
+    assert!(output.status.success());
+
+    let output = pngme(&["decode", path, "ruSt"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: first line\nsecond line\n\n"
+    );
+}
+
+#[test]
+fn test_encode_input_file() {
+    let path = fixture_copy("input-file", |_| {});
+    let input = TempPath::new("input.bin");
+    fs::write(&input, b"a\0b\0\x01").unwrap();
+
+    let output = pngme(&[
+        "encode",
+        path.to_str().unwrap(),
+        "BiNa",
+        "--input-file",
+        input.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+
+    let output = pngme(&["decode", path.to_str().unwrap(), "BiNa"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"Hidden message: a\0b\0\x01\n");
+
+    let output = pngme(&[
+        "encode",
+        path.to_str().unwrap(),
+        "BiNa",
+        "hi",
+        "--input-file",
+        "x",
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_encode_message_from_stdin() {
+    let path = fixture_copy("stdin", |_| {});
+    let file = path.to_str().unwrap();
+
+    let output = pngme_with_stdin(&["encode", file, "ruSt", "-"], b"line one\nline two\n");
+    assert!(output.status.success());
+    let output = pngme(&["decode", file, "ruSt"]);
+    assert_eq!(output.stdout, b"Hidden message: line one\nline two\n\n");
+
+    let args = ["encode", file, "ruSu", "-", "--strip-trailing-newline"];
+    let output = pngme_with_stdin(&args, b"piped\n");
+    assert!(output.status.success());
+    let output = pngme(&["decode", file, "ruSu"]);
+    assert_eq!(output.stdout, b"Hidden message: piped\n");
+}
+
+#[test]
+fn test_encode_inserts_before_iend() {
+    let path = fixture_copy("before-iend", |_| {});
+    let file = path.to_str().unwrap();
+    assert!(pngme(&["encode", file, "ruSt", "hidden"]).status.success());
+
+    let bytes = fs::read(&path).unwrap();
+    assert_eq!(&bytes[bytes.len() - 8..bytes.len() - 4], b"IEND");
+    assert!(pngme(&["verify", file]).status.success());
+
+    assert!(pngme(&["encode", file, "ruSt", "again", "--append"])
+        .status
+        .success());
+    // The last chunk is now the ruSt chunk, with 5 bytes of data before its CRC
+    let bytes = fs::read(&path).unwrap();
+    assert_eq!(&bytes[bytes.len() - 13..bytes.len() - 4], b"ruStagain");
+}
+
+#[test]
+fn test_encode_placement() {
+    let path = fixture_copy("placement", |_| {});
+    let file = path.to_str().unwrap();
+    let encode = |args: &[&str]| pngme(&[&["encode", file, "ruSt", "hi"], args].concat());
+
+    assert!(encode(&["--after", "IHDR"]).status.success());
+    assert!(encode(&["--index", "3"]).status.success());
+    assert!(!encode(&["--after", "IEND"]).status.success());
+    assert!(!encode(&["--index", "0"]).status.success());
+    assert!(!encode(&["--index", "2", "--append"]).status.success());
+
+    let output = pngme(&["list", file]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let types: Vec<&str> = stdout.lines().map(|line| &line[3..7]).collect();
+    assert_eq!(
+        types,
+        ["IHDR", "ruSt", "sRGB", "ruSt", "gAMA", "pHYs", "IDAT", "RuSt", "IEND"]
+    );
+}
+
+#[test]
+fn test_encode_stdout() {
+    let output = pngme(&["encode", FIXTURE, "ruSt", "piped", "--stdout"]);
+    assert!(output.status.success());
+    assert!(output.stdout.starts_with(b"\x89PNG\r\n\x1a\n"));
+    assert_eq!(
+        output.stdout.len(),
+        fs::read(FIXTURE).unwrap().len() + 12 + 5
+    );
+}
+
+#[test]
+fn test_encode_with_hash() {
+    let path = fixture_copy("with-hash", |_| ());
+    let path = path.to_str().unwrap();
+
+    let output = pngme(&["encode", path, "ruSt", "secret", "--with-hash"]);
+    assert!(output.status.success());
+
+    let output = pngme(&["decode", path, "ruSt", "--verify-hash"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: secret\n"
+    );
+    assert!(output.stderr.is_empty());
+
+    // A trailer whose hash doesn't match the message, in a chunk with a valid CRC
+    let mut tampered = b"secret".to_vec();
+    tampered.extend(b"SHA2");
+    tampered.extend([b'x'; 32]);
+    let output = pngme_with_stdin(&["encode", path, "reSt", "--message0"], &tampered);
+    assert!(output.status.success());
+
+    let output = pngme(&["decode", path, "reSt", "--verify-hash"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: secret\n"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("integrity hash does not match the message"));
+}
+
+#[test]
+fn test_manifest_detects_image_changes() {
+    let dir = TempPath::new("manifest");
+    fs::create_dir_all(dir.join("nested")).unwrap();
+    let image = dir.join("image.png");
+    let nested = dir.join("nested").join("other.png");
+    fs::copy(FIXTURE, &image).unwrap();
+    fs::copy(FIXTURE, &nested).unwrap();
+
+    let output = pngme(&["manifest", dir.to_str().unwrap()]);
+    assert!(output.status.success());
+    let manifest = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(manifest.lines().count(), 2);
+    let manifest_path = dir.join("manifest.txt");
+    fs::write(&manifest_path, &manifest).unwrap();
+
+    // Adding a tEXt chunk keeps the fingerprint
+    let output = pngme(&["encode", image.to_str().unwrap(), "ruSt", "Dice"]);
+    assert!(output.status.success());
+    let output = pngme(&[
+        "migrate",
+        image.to_str().unwrap(),
+        "ruSt",
+        "--keyword",
+        "Title",
+    ]);
+    assert!(output.status.success());
+    let output = pngme(&["manifest", "--check", manifest_path.to_str().unwrap()]);
+    assert!(output.status.success());
+
+    // Changing a byte of image data, with a matching CRC, changes it
+    let mut bytes = fs::read(&nested).unwrap();
+    bytes[91] ^= 0x01;
+    let crc = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC).checksum(&bytes[87..4772]);
+    bytes[4772..4776].copy_from_slice(&crc.to_be_bytes());
+    fs::write(&nested, bytes).unwrap();
+
+    let output = pngme(&["manifest", "--check", manifest_path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("image.png: OK"));
+    assert!(stdout.contains("other.png: CHANGED"));
+}
+
+#[test]
+fn test_encode_pad_to() {
+    let path = fixture_copy("pad-to", |_| ());
+    let path = path.to_str().unwrap();
+
+    let output = pngme(&["encode", path, "ruSt", "short", "--pad-to", "256"]);
+    assert!(output.status.success());
+
+    let output = pngme(&["decode", path, "ruSt", "--length-only"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "256\n");
+    let output = pngme(&["decode", path, "ruSt", "--padded"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: short\n"
+    );
+
+    let output = pngme(&["encode", path, "ruSt", "too long", "--pad-to", "8"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_encode_seed_is_reproducible() {
+    let encode = |name, seed| {
+        let path = fixture_copy(name, |_| ());
+        let file = path.to_str().unwrap();
+        let output = pngme(&[
+            "encode", file, "ruSt", "short", "--pad-to", "64", "--seed", seed,
+        ]);
+        assert!(output.status.success());
+        fs::read(path).unwrap()
+    };
+
+    assert_eq!(encode("seed-a", "7"), encode("seed-b", "7"));
+    assert_ne!(encode("seed-c", "7"), encode("seed-d", "8"));
+}
+
+#[test]
+fn test_verify_data() {
+    let expected = TempPath::new("expected.bin");
+    let expected_path = expected.to_str().unwrap();
+
+    fs::write(&expected, b"hey").unwrap();
+    let output = pngme(&["verify-data", FIXTURE, "RuSt", expected_path]);
+    assert!(output.status.success());
+
+    fs::write(&expected, b"hex").unwrap();
+    let output = pngme(&["verify-data", FIXTURE, "RuSt", expected_path]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("differs from"));
+    assert!(stderr.contains("at byte 2"));
+}
+
+#[test]
+fn test_extract_all_concat() {
+    let path = fixture_copy("extract-all", |_| ());
+    let path = path.to_str().unwrap();
+    let extracted = TempPath::new("extract-all.bin");
+    let output_path = extracted.to_str().unwrap();
+
+    // Split the 4681 bytes of image data into three IDAT chunks
+    let output = pngme(&["rechunk", path, "--max-idat", "2000"]);
+    assert!(output.status.success());
+    let output = pngme(&["print", path]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.matches("IDAT").count(), 3);
+
+    let output = pngme(&["extract", path, "IDAT", output_path, "--all", "--concat"]);
+    assert!(output.status.success());
+    let original = fs::read(FIXTURE).unwrap();
+    assert_eq!(fs::read(&extracted).unwrap(), original[91..4772]);
+}
+
+#[test]
+fn test_extract_raw_nth() {
+    let path = fixture_copy("extract-raw", |_| ());
+    let path = path.to_str().unwrap();
+    let extracted = TempPath::new("extract-raw.bin");
+    let output_path = extracted.to_str().unwrap();
+    assert!(pngme(&["encode", path, "RuSt", "two"]).status.success());
+
+    let output = pngme(&["extract", path, "RuSt", output_path, "--raw"]);
+    assert!(output.status.success());
+    let original = fs::read(FIXTURE).unwrap();
+    assert_eq!(fs::read(&extracted).unwrap(), original[4776..4791]);
+
+    let output = pngme(&["extract", path, "RuSt", output_path, "--nth", "1"]);
+    assert!(output.status.success());
+    assert_eq!(fs::read(&extracted).unwrap(), b"two");
+
+    let output = pngme(&["extract", path, "RuSt", output_path, "--nth", "2"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_quiet_errors() {
+    let output = pngme(&["decode", FIXTURE, "abCD"]);
+    assert!(!output.status.success());
+    assert!(!output.stderr.is_empty());
+
+    let output = pngme(&["decode", FIXTURE, "abCD", "--quiet-errors"]);
+    assert!(!output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_decode_all_numbered() {
+    let path = fixture_copy("decode-numbered", |_| {});
+    let input = TempPath::new("numbered.bin");
+    fs::write(&input, [b'o', b'k', 0xff]).unwrap();
+    let file = path.to_str().unwrap();
+    let args = [
+        "encode",
+        file,
+        "RuSt",
+        "--input-file",
+        input.to_str().unwrap(),
+    ];
+    assert!(pngme(&args).status.success());
+    assert!(pngme(&["encode", file, "RuSt", "there"]).status.success());
+
+    let output = pngme(&["decode", file, "RuSt", "--all"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "0: hey\n1: <3 bytes> 6f6bff\n2: there\n"
+    );
+
+    let output = pngme(&["decode", file, "RuSt", "--all", "--max-messages", "1"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "0: hey\n... (2 more)\n"
+    );
+}
+
+#[test]
+fn test_binary_message_round_trip() {
+    let path = fixture_copy("binary-round-trip", |_| {});
+    let input = TempPath::new("blob.bin");
+    let saved = TempPath::new("blob-out.bin");
+    let blob: Vec<u8> = (0..=255).chain([0, 0, b'\n']).collect();
+    fs::write(&input, &blob).unwrap();
+    let file = path.to_str().unwrap();
+    let (input_arg, saved_arg) = (input.to_str().unwrap(), saved.to_str().unwrap());
+
+    let args = ["encode", file, "BlOb", "--message-file", input_arg];
+    assert!(pngme(&args).status.success());
+    let output = pngme(&["decode", file, "BlOb", "--output", saved_arg]);
+    assert!(output.status.success());
+    assert_eq!(fs::read(&saved).unwrap(), blob);
+
+    // A message from a file can still be saved into a copy
+    let copy = TempPath::new("blob-copy.png");
+    let copy_arg = copy.to_str().unwrap();
+    let args = [
+        "encode",
+        FIXTURE,
+        "BlOb",
+        "--input-file",
+        input_arg,
+        "--output",
+        copy_arg,
+    ];
+    assert!(pngme(&args).status.success());
+    let output = pngme(&["decode", copy_arg, "BlOb", "--output", saved_arg]);
+    assert!(output.status.success());
+    assert_eq!(fs::read(&saved).unwrap(), blob);
+
+    let output = pngme_with_stdin(&["encode", file, "StIn", "--message-stdin"], &blob);
+    assert!(output.status.success());
+    let output = pngme(&["decode", file, "StIn", "--output", saved_arg]);
+    assert!(output.status.success());
+    assert_eq!(fs::read(&saved).unwrap(), blob);
+}
+
+#[test]
+fn test_pipe_through_stdin_and_stdout() {
+    let original = fs::read(FIXTURE).unwrap();
+    let output = pngme_with_stdin(&["encode", "-", "ruSt", "piped"], &original);
+    assert!(output.status.success());
+    let encoded = output.stdout;
+    assert_eq!(&encoded[..8], &original[..8]);
+
+    let output = pngme_with_stdin(&["decode", "-", "ruSt"], &encoded);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: piped\n"
+    );
+
+    let output = pngme_with_stdin(&["remove", "-", "ruSt"], &encoded);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, original);
+
+    let output = pngme_with_stdin(&["encode", "-", "ruSt", "-"], &original);
+    assert!(!output.status.success());
+
+    // Commands that write the file back send it to stdout too, rather than to a file named `-`
+    let output = pngme_with_stdin(&["strip", "-"], &encoded);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("Removed 4 chunk(s)"));
+    let output = pngme_with_stdin(&["decode", "-", "ruSt"], &output.stdout);
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("chunk does not exist"));
+    let output = pngme_with_stdin(&["meta", "set", "-", "Title=piped"], &original);
+    assert!(output.status.success());
+    let output = pngme_with_stdin(&["meta", "get", "-", "Title"], &output.stdout);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "piped\n");
+    assert!(!std::path::Path::new("-").exists());
+
+    // A file skipped by --since still comes out unchanged
+    let args = [
+        "encode",
+        "-",
+        "ruSt",
+        "skipped",
+        "--since",
+        "2000-01-01",
+        "--no-time-policy",
+        "skip",
+    ];
+    let output = pngme_with_stdin(&args, &original);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, original);
+}
+
+#[test]
+fn test_encode_replace() {
+    let path = fixture_copy("encode-replace", |_| {});
+    let file = path.to_str().unwrap();
+
+    let output = pngme(&["encode", file, "RuSt", "updated", "--replace"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    let output = pngme(&["decode", file, "RuSt"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: updated\n"
+    );
+    let output = pngme(&["list", file]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.lines().nth(5).unwrap().contains("RuSt"));
+
+    // With no chunk to replace, the chunk is added as usual
+    let output = pngme(&[
+        "encode",
+        file,
+        "ruSt",
+        "new",
+        "--replace",
+        "--after",
+        "IHDR",
+    ]);
+    assert!(output.status.success());
+    let output = pngme(&["decode", file, "ruSt"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Hidden message: new\n"
+    );
+
+    let output = pngme(&["encode", file, "ruSt", "another"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("already has 1 ruSt chunk(s)"));
+}
+
+#[test]
+fn test_info_reports_ihdr_fields() {
+    let output = pngme(&["info", FIXTURE]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "7 chunks, 4803 bytes\ndimensions: 50x50\nbit depth: 8\ncolour type: 6 (RGBA)\n\
+         interlace: none\n"
+    );
+}
+
+#[test]
+fn test_batch_encode_decode_remove() {
+    let first = fixture_copy("batch-first", |_| {});
+    let second = fixture_copy("batch-second", |_| {});
+    let missing = TempPath::new("batch-missing.png");
+    let (first, second, missing) = (
+        first.to_str().unwrap(),
+        second.to_str().unwrap(),
+        missing.to_str().unwrap(),
+    );
+
+    let output = pngme(&["encode", first, "ruSt", "batch", "--also", second, missing]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("2 succeeded, 1 failed: "));
+    assert!(stderr.contains(missing));
+
+    let output = pngme(&["decode", first, "ruSt", "--also", second]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{first}:\nHidden message: batch\n{second}:\nHidden message: batch\n")
+    );
+
+    // With --output-dir the originals are left alone
+    let dir = TempPath::new("batch-out");
+    fs::create_dir_all(&dir).unwrap();
+    let output = pngme(&[
+        "remove",
+        first,
+        "ruSt",
+        "--also",
+        second,
+        "--output-dir",
+        dir.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    for file in [first, second] {
+        let copy = dir.join(std::path::Path::new(file).file_name().unwrap());
+        let output = pngme(&["decode", copy.to_str().unwrap(), "ruSt"]);
+        assert!(!output.status.success());
+        let output = pngme(&["decode", file, "ruSt"]);
+        assert!(output.status.success());
+    }
+
+    // Two inputs with the same file name can't both go into the output directory
+    let dir = TempPath::new("batch-same-name");
+    let (a, b, out) = (dir.join("a"), dir.join("b"), dir.join("out"));
+    for subdir in [&a, &b, &out] {
+        fs::create_dir_all(subdir).unwrap();
+    }
+    fs::copy(FIXTURE, a.join("s.png")).unwrap();
+    fs::copy(FIXTURE, b.join("s.png")).unwrap();
+    let output = pngme(&[
+        "encode",
+        a.join("s.png").to_str().unwrap(),
+        "ruSt",
+        "x",
+        "--also",
+        b.join("s.png").to_str().unwrap(),
+        "--output-dir",
+        out.to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("1 succeeded, 1 failed"));
+
+    let output = pngme(&[
+        "encode", first, "ruSt", "x", "-o", "out.png", "--also", second,
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("cannot be used with"));
+}
+
+#[test]
+fn test_encode_since_only_changes_newer_files() {
+    // Give each copy a tIME chunk before the IEND chunk
+    let with_time = |name, time: [u8; 7]| {
+        fixture_copy(name, |bytes| {
+            let mut chunk = vec![0, 0, 0, 7];
+            chunk.extend(b"tIME");
+            chunk.extend(time);
+            let crc = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC).checksum(&chunk[4..]);
+            chunk.extend(crc.to_be_bytes());
+            bytes.splice(4791..4791, chunk);
+        })
+    };
+    let older = with_time("since-older", [0x07, 0xe7, 12, 31, 12, 0, 0]);
+    let newer = with_time("since-newer", [0x07, 0xe8, 1, 2, 12, 0, 0]);
+    let (older, newer) = (older.to_str().unwrap(), newer.to_str().unwrap());
+
+    let args = [
+        "encode",
+        older,
+        "ruSt",
+        "new",
+        "--since",
+        "2024-01-01",
+        "--also",
+        newer,
+    ];
+    let output = pngme(&args);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains(&format!("Skipping {}", older)));
+
+    assert!(!pngme(&["decode", older, "ruSt"]).status.success());
+    assert!(pngme(&["decode", newer, "ruSt"]).status.success());
+}